@@ -17,18 +17,18 @@ const WINS: [[usize; 3]; 8] = [
 ];
 
 #[derive(Clone)]
-struct TTT {
+struct TicTacToe {
     board: [usize; 9],
     turn: usize
 }
 
-impl TTT {
+impl TicTacToe {
     fn new() -> Self {
         Self { board: [0; 9], turn: 1 }
     }
 }
 
-impl Game<usize> for TTT {
+impl Game<usize> for TicTacToe {
     fn get_num_players(&self) -> usize {
         2
     }
@@ -40,7 +40,7 @@ impl Game<usize> for TTT {
     fn get_choices(&self) -> Vec<usize> {
         let mut choices = Vec::new();
 
-        if self.get_winner() != 0 { return choices };
+        if self.get_outcome() != Outcome::Ongoing { return choices };
 
         for i in 0..9 {
             if self.board[i] == 0 { choices.push(i) }
@@ -54,7 +54,7 @@ impl Game<usize> for TTT {
         self.turn = if self.turn == 1 { 2 } else { 1 };
     }
 
-    fn get_winner(&self) -> usize {
+    fn get_outcome(&self) -> Outcome {
         let mut winner = 0;
 
         if WINS.iter().any(|win| {
@@ -64,14 +64,16 @@ impl Game<usize> for TTT {
             self.board[win[1]] == self.board[win[2]] && 
             self.board[win[0]] != 0
         }) {
-            winner
+            Outcome::Win(winner)
+        } else if self.board.iter().all(|&player_id| player_id != 0) {
+            Outcome::Draw
         } else {
-            0
+            Outcome::Ongoing
         }
     }
 }
 
-impl std::fmt::Display for TTT {
+impl std::fmt::Display for TicTacToe {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let b: Vec<&str> = self.board.iter().map(|player_id| {
             match player_id {
@@ -93,7 +95,7 @@ impl std::fmt::Display for TTT {
 }
 
 fn main() {
-    let ttt = TTT::new();
+    let ttt = TicTacToe::new();
 
     let mcts = MCTS::new(&ttt, ExploitVsExplore::UCB1(1.41));
 
@@ -110,19 +112,20 @@ fn main() {
     }
 }
 
-fn play_ttt_without_tree(ttt: &TTT, mcts: &MCTS<TTT, usize>) {
+fn play_ttt_without_tree(ttt: &TicTacToe, mcts: &MCTS<TicTacToe, usize>) {
     let mut clone = ttt.clone();
     println!("{}", clone);
 
+    // None means the game is over here, in a game with chance moves it can also mean chance is to move
     while let Some(choice) = mcts.advise(&clone, 1000) {
         clone.choose(&choice);
         println!("{}", clone);
     }
 
-    println!("Winner: {}", clone.get_winner())
+    println!("Outcome: {:?}", clone.get_outcome())
 }
 
-fn play_ttt_with_tree(ttt: &TTT, mcts: &MCTS<TTT, usize>) {
+fn play_ttt_with_tree(ttt: &TicTacToe, mcts: &MCTS<TicTacToe, usize>) {
     let mut clone = ttt.clone();
     let mut tree = monte::Node::default(clone.clone(), clone.get_num_players());
     println!("{} \n{}", tree, clone);
@@ -133,5 +136,5 @@ fn play_ttt_with_tree(ttt: &TTT, mcts: &MCTS<TTT, usize>) {
         println!("{} \n{}", tree, clone);
     }
 
-    println!("Winner: {}", clone.get_winner())
+    println!("Outcome: {:?}", clone.get_outcome())
 }
\ No newline at end of file
@@ -0,0 +1,184 @@
+use std::marker::PhantomData;
+use std::time::Instant;
+use rand::Rng;
+
+use crate::{Budget, Evaluator, ExploitVsExplore, Game, Outcome, RandomPlayout, SelectionPolicy, best_index, drop_tree, sample_weighted};
+
+// single observer information set mcts: every cycle searches a different determinization of the hidden
+// information, so the tree is built over what the observer can actually see rather than the true state
+pub struct ISMCTS<Game_, Choice, Eval = RandomPlayout> where Choice: Clone, Game_: Game<Choice> + Clone, Eval: Evaluator<Game_, Choice> {
+    players: usize,
+    exploit_vs_explore: ExploitVsExplore,
+    evaluator: Eval,
+    __anoying: (PhantomData<Choice>, PhantomData<Game_>)
+}
+
+impl<Game_, Choice> ISMCTS<Game_, Choice> where Choice: Clone + PartialEq, Game_: Game<Choice> + Clone {
+    pub fn new(initial_game_state: &Game_, exploit_vs_explore: ExploitVsExplore) -> Self {
+        Self::with_evaluator(initial_game_state, exploit_vs_explore, RandomPlayout::new())
+    }
+}
+
+impl<Game_, Choice, Eval> ISMCTS<Game_, Choice, Eval> where Choice: Clone + PartialEq, Game_: Game<Choice> + Clone, Eval: Evaluator<Game_, Choice> {
+    pub fn with_evaluator(initial_game_state: &Game_, exploit_vs_explore: ExploitVsExplore, evaluator: Eval) -> Self {
+        let players = initial_game_state.get_num_players();
+
+        Self { players, exploit_vs_explore, evaluator, __anoying: (PhantomData, PhantomData) }
+    }
+
+    // searches one determinization, leaving the index of each child it went through in `path`
+    fn ismcts(&self, tree: &mut InfoSetNode<Choice>, game_state: &mut Game_, path: &mut Vec<usize>, nodes: &mut usize) {
+        path.clear();
+
+        let values = self.descend(tree, game_state, path, nodes);
+
+        Self::backup(tree, path, &values);
+    }
+
+    // follows the determinization down the tree, adding a node for the first choice it meets that isn't in the
+    // tree yet (and evaluating it) or stopping at the end of the game. returns the value each player got
+    fn descend(&self, tree: &mut InfoSetNode<Choice>, game_state: &mut Game_, path: &mut Vec<usize>, nodes: &mut usize) -> Vec<f64> {
+        let mut node = tree;
+
+        loop {
+            if game_state.get_outcome() != Outcome::Ongoing {
+                return game_state.get_rewards();
+            }
+
+            if let Some(outcomes) = game_state.get_chance_outcomes() {
+                let index = sample_weighted(outcomes.iter().map(|(_, weight)| *weight), &mut rand::thread_rng());
+                let outcome = &outcomes[index].0;
+
+                let next = match node.next.iter().position(|next| next.choice.as_ref() == Some(outcome)) {
+                    Some(next) => next,
+                    None => {
+                        node.next.push(InfoSetNode::new(Some(outcome.clone()), self.players));
+                        *nodes += 1;
+
+                        node.next.len() - 1
+                    }
+                };
+
+                game_state.choose(outcome);
+                path.push(next);
+                node = &mut node.next[next];
+
+                continue;
+            }
+
+            let choices = game_state.get_choices();
+
+            if choices.is_empty() {
+                return game_state.get_rewards();
+            }
+
+            // only the children that are legal in this determinization can be picked, and they're the ones that
+            // count this visit as being available
+            let compatible: Vec<(usize, usize)> = node.next.iter().enumerate().filter_map(|(index, next)| {
+                choices.iter().position(|choice| next.choice.as_ref() == Some(choice)).map(|choice| (index, choice))
+            }).collect();
+
+            for &(index, _) in compatible.iter() {
+                node.next[index].availability += 1.0;
+            }
+
+            if compatible.len() < choices.len() {
+                let untried: Vec<&Choice> = choices.iter().enumerate()
+                    .filter(|&(index, _)| !compatible.iter().any(|&(_, choice)| choice == index))
+                    .map(|(_, choice)| choice)
+                    .collect();
+                let choice = untried[rand::thread_rng().gen_range(0..untried.len())];
+
+                let mut next = InfoSetNode::new(Some(choice.clone()), self.players);
+                *nodes += 1;
+
+                game_state.choose(choice);
+                next.availability += 1.0;
+
+                node.next.push(next);
+                path.push(node.next.len() - 1);
+
+                return self.evaluator.evaluate(game_state).values;
+            }
+
+            let player = game_state.get_turn();
+            let priors = game_state.get_priors(&choices);
+            let scores = compatible.iter().map(|&(index, choice)| {
+                let next = &node.next[index];
+
+                (index, self.exploit_vs_explore.score_with_squares(next.wins[player - 1], next.squared_wins[player - 1], next.visits + 0.00001, next.availability, priors[choice]))
+            });
+            let next = best_index(scores, &mut rand::thread_rng()).unwrap();
+
+            game_state.choose(node.next[next].choice.as_ref().unwrap());
+            path.push(next);
+            node = &mut node.next[next];
+        }
+    }
+
+    fn backup(tree: &mut InfoSetNode<Choice>, path: &[usize], values: &[f64]) {
+        let mut node = tree;
+        node.update(values);
+
+        for &index in path {
+            node = &mut node.next[index];
+            node.update(values);
+        }
+    }
+
+    // searches from the point of view of the player to move
+    pub fn advise(&self, game_state: &Game_, cycles: usize) -> Option<Choice> {
+        self.advise_with_budget(game_state, game_state.get_turn(), &Budget::new().with_cycles(cycles))
+    }
+
+    pub fn advise_with_budget(&self, game_state: &Game_, observer: usize, budget: &Budget) -> Option<Choice> {
+        if game_state.get_chance_outcomes().is_some() { return None };
+
+        let mut rng = rand::thread_rng();
+        let mut tree = InfoSetNode::new(None, self.players);
+        let start = Instant::now();
+        let mut path = Vec::new();
+        let mut nodes = 1;
+        let mut cycles = 0;
+
+        while !budget.is_exhausted(cycles, start, nodes) {
+            let mut determinization = game_state.determinize(observer, &mut rng);
+
+            self.ismcts(&mut tree, &mut determinization, &mut path, &mut nodes);
+            cycles += 1;
+        }
+
+        // the most visited choice, win rates aren't comparable when the choices were available a different
+        // number of times
+        tree.next.iter()
+            .max_by(|a, b| a.visits.total_cmp(&b.visits))
+            .and_then(|next| next.choice.clone())
+    }
+}
+
+struct InfoSetNode<Choice> {
+    choice: Option<Choice>,
+    wins: Vec<f64>,
+    squared_wins: Vec<f64>,
+    visits: f64,
+    availability: f64,
+    next: Vec<InfoSetNode<Choice>>
+}
+
+impl<Choice> InfoSetNode<Choice> {
+    fn new(choice: Option<Choice>, players: usize) -> Self {
+        InfoSetNode { choice, wins: vec![0.0; players], squared_wins: vec![0.0; players], visits: 0.0, availability: 0.0, next: Vec::new() }
+    }
+
+    fn update(&mut self, values: &[f64]) {
+        self.wins.iter_mut().zip(values.iter()).for_each(|(win, value)| *win += value);
+        self.squared_wins.iter_mut().zip(values.iter()).for_each(|(squared_win, value)| *squared_win += value * value);
+        self.visits += 1.0;
+    }
+}
+
+impl<Choice> Drop for InfoSetNode<Choice> {
+    fn drop(&mut self) {
+        drop_tree(std::mem::take(&mut self.next), |node: &mut Self| std::mem::take(&mut node.next));
+    }
+}
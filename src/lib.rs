@@ -1,5 +1,19 @@
+use std::collections::HashMap;
 use std::marker::PhantomData;
-use rand::Rng;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use rand::{Rng, RngCore};
+
+mod ismcts;
+mod nested;
+mod simultaneous;
+mod single_player;
+
+pub use ismcts::ISMCTS;
+pub use nested::{NestedMonteCarlo, NRPA};
+pub use single_player::{SinglePlayerMCTS, Solution};
+pub use simultaneous::{DecoupledMCTS, DecoupledPolicy};
 
 pub struct GameInfo {
     pub input_size: usize, 
@@ -13,197 +27,1772 @@ pub trait Game<Choice> where Choice: Clone {
 
     fn get_choices(&self) -> Vec<Choice>;
     fn choose(&mut self, choice: &Choice);
-    fn get_winner(&self) -> usize;
-    fn random_play(&mut self) -> usize {
+    // Ongoing for as long as the game continues
+    fn get_outcome(&self) -> Outcome;
+
+    // a hash of the position, positions with the same key share their statistics in the search
+    // (so different move orders reaching the same position aren't searched separately)
+    fn get_key(&self) -> Option<u64> {
+        None
+    }
+
+    // Some when the next move is made by chance rather than a player (dice, card draws, ...), listing each
+    // outcome with its weight. outcomes are applied with `choose`, and `get_choices` isn't used for these states
+    fn get_chance_outcomes(&self) -> Option<Vec<(Choice, f64)>> {
+        None
+    }
+
+    // Some when every player chooses at the same time, listing the choices of each player (player 1 first). a
+    // player with nothing to do gets an empty list. only `DecoupledMCTS` searches these phases
+    fn get_simultaneous_choices(&self) -> Option<Vec<Vec<Choice>>> {
+        None
+    }
+
+    // applies one choice per player (None for players without a choice) made in a simultaneous phase
+    fn choose_simultaneous(&mut self, choices: &[Option<Choice>]) {
+        for choice in choices.iter().flatten() {
+            self.choose(choice);
+        }
+    }
+
+    // a copy of the game with everything the observer can't see (opponents' hands, the deck order, ...) filled in
+    // at random, consistent with what they've seen so far. used by `ISMCTS`, perfect information games can leave it
+    fn determinize(&self, _observer: usize, _rng: &mut dyn RngCore) -> Self where Self: Sized + Clone {
+        self.clone()
+    }
+
+    // one of the choices at random, used by progressive widening. games with too many choices to list can override
+    // it to sample one directly (None when there are no choices)
+    fn sample_choice(&self, rng: &mut dyn RngCore) -> Option<Choice> {
         let mut choices = self.get_choices();
+
+        if choices.is_empty() { return None };
+
+        Some(choices.swap_remove(rng.gen_range(0..choices.len())))
+    }
+
+    // prior probability of each choice (one per choice, in the same order), used by PUCT (uniform unless overridden)
+    fn get_priors(&self, choices: &[Choice]) -> Vec<f64> {
+        vec![1.0 / choices.len() as f64; choices.len()]
+    }
+
+    // the value each player gets at the end of the game (player 1 first). by default 1 for the winner or an even
+    // split on a draw, games scored by points, margins or placings can return those instead (the exploration
+    // constants are tuned for values between 0 and 1, so scale them to roughly that range)
+    fn get_rewards(&self) -> Vec<f64> {
+        self.get_outcome().rewards(self.get_num_players())
+    }
+
+    // plays random moves until the game ends, returning the rewards
+    fn random_play(&mut self) -> Vec<f64> {
+        self.playout(None, None)
+    }
+
+    // heuristic value of a position for each player, used when a playout is cut short
+    fn evaluate(&self) -> Vec<f64> {
+        self.get_rewards()
+    }
+
+    // plays at most max_length random moves, then falls back to `evaluate` if the game hasn't ended
+    fn random_play_limited(&mut self, max_length: usize) -> Vec<f64> {
+        self.playout(Some(max_length), None)
+    }
+
+    // the playout behind `random_play` and `random_play_limited`, which also adds every choice a player makes to
+    // `played` (with the player who made it) when rave asks for them. override this one to play out with a policy
+    // of your own, so searches with and without rave both use it
+    fn playout(&mut self, max_length: Option<usize>, mut played: Option<&mut Vec<(usize, Choice)>>) -> Vec<f64> {
         let mut rng = rand::thread_rng();
+        let mut length = 0;
+
+        while !is_over(self) {
+            if max_length.is_some_and(|max_length| length >= max_length) { return self.evaluate() };
+
+            random_step(self, &mut rng, played.as_deref_mut());
+            length += 1;
+        }
+
+        self.get_rewards()
+    }
+}
+
+// games that can reverse `choose`, so the search can walk one state down the tree and back up again instead of
+// keeping a copy in every node (see `MCTS::with_undo`)
+pub trait Undo<Choice>: Game<Choice> where Choice: Clone {
+    fn undo(&mut self, choice: &Choice);
+}
+
+// plays a uniformly random choice (one for every player in a simultaneous phase), or an outcome drawn by weight
+// in a chance state. the choices the players made are added to `played` along with who made them. returns false
+// once the game is over
+fn random_step<Game_, Choice>(game_state: &mut Game_, rng: &mut impl Rng, played: Option<&mut Vec<(usize, Choice)>>) -> bool where Game_: Game<Choice> + ?Sized, Choice: Clone {
+    if game_state.get_outcome() != Outcome::Ongoing { return false };
+
+    if let Some(mut outcomes) = game_state.get_chance_outcomes() {
+        if outcomes.is_empty() { return false };
+
+        let index = sample_weighted(outcomes.iter().map(|(_, weight)| *weight), rng);
+        game_state.choose(&outcomes.swap_remove(index).0);
+
+        return true;
+    }
+
+    if let Some(player_choices) = game_state.get_simultaneous_choices() {
+        if player_choices.iter().all(|choices| choices.is_empty()) { return false };
+
+        let joint: Vec<Option<Choice>> = player_choices.iter().map(|choices| {
+            if choices.is_empty() { None } else { Some(choices[rng.gen_range(0..choices.len())].clone()) }
+        }).collect();
+        game_state.choose_simultaneous(&joint);
+
+        if let Some(played) = played {
+            played.extend(joint.into_iter().enumerate().filter_map(|(player, choice)| choice.map(|choice| (player + 1, choice))));
+        }
+
+        return true;
+    }
+
+    let choices = game_state.get_choices();
+
+    if choices.is_empty() { return false };
+
+    let choice = &choices[rng.gen_range(0..choices.len())];
+
+    if let Some(played) = played {
+        played.push((game_state.get_turn(), choice.clone()));
+    }
+
+    game_state.choose(choice);
+
+    true
+}
+
+fn is_over<Game_, Choice>(game_state: &Game_) -> bool where Game_: Game<Choice> + ?Sized, Choice: Clone {
+    game_state.get_outcome() != Outcome::Ongoing || match (game_state.get_chance_outcomes(), game_state.get_simultaneous_choices()) {
+        (Some(outcomes), _) => outcomes.is_empty(),
+        (None, Some(player_choices)) => player_choices.iter().all(|choices| choices.is_empty()),
+        (None, None) => game_state.get_choices().is_empty()
+    }
+}
+
+// the index with the highest score, ties broken at random. None when there are no scores
+fn best_index(scores: impl Iterator<Item = (usize, f64)>, rng: &mut impl Rng) -> Option<usize> {
+    let mut best = (Vec::new(), f64::NEG_INFINITY);
+
+    for (index, score) in scores {
+        if score > best.1 {
+            best = (vec![index], score);
+        } else if score == best.1 {
+            best.0.push(index);
+        }
+    }
+
+    if best.0.is_empty() { return None };
+
+    Some(best.0[rng.gen_range(0..best.0.len())])
+}
+
+// drops a tree with an explicit stack rather than recursively, a long enough line of nodes would overflow the
+// call stack. `children` takes the children out of a node
+fn drop_tree<Node_, Children>(roots: Children, children: impl Fn(&mut Node_) -> Children) where Children: IntoIterator<Item = Node_> {
+    let mut stack: Vec<Node_> = roots.into_iter().collect();
+
+    while let Some(mut node) = stack.pop() {
+        stack.extend(children(&mut node));
+    }
+}
+
+fn sample_weighted(weights: impl Iterator<Item = f64> + Clone, rng: &mut impl Rng) -> usize {
+    let mut remaining = rng.gen::<f64>() * weights.clone().sum::<f64>();
+    let mut last = 0;
+
+    for (i, weight) in weights.enumerate() {
+        if remaining < weight { return i };
+
+        remaining -= weight;
+        last = i;
+    }
+
+    last
+}
+
+// the result of evaluating a newly reached node: a value per player and optionally priors over
+// `get_choices()` (in the same order), which replace `Game::get_priors` when the node is expanded
+pub struct Evaluation {
+    pub values: Vec<f64>,
+    pub priors: Option<Vec<f64>>
+}
+
+impl Evaluation {
+    pub fn new(values: Vec<f64>) -> Self {
+        Self { values, priors: None }
+    }
+
+    pub fn with_priors(values: Vec<f64>, priors: Vec<f64>) -> Self {
+        Self { values, priors: Some(priors) }
+    }
+}
+
+pub trait Evaluator<Game_, Choice> where Choice: Clone, Game_: Game<Choice> + Clone {
+    fn evaluate(&self, game_state: &Game_) -> Evaluation;
+
+    // like `evaluate`, but also adds every choice it plays to `played` along with the player who made it. used by
+    // RAVE, evaluators that don't play the game out can leave it
+    fn evaluate_recorded(&self, game_state: &Game_, _played: &mut Vec<(usize, Choice)>) -> Evaluation {
+        self.evaluate(game_state)
+    }
+}
+
+// the default evaluator, plays random moves until the game ends or max_length moves have been played
+#[derive(Clone, Copy, Default)]
+pub struct RandomPlayout {
+    pub max_length: Option<usize>
+}
+
+impl RandomPlayout {
+    pub fn new() -> Self {
+        Self { max_length: None }
+    }
+
+    pub fn limited(max_length: usize) -> Self {
+        Self { max_length: Some(max_length) }
+    }
+}
+
+impl<Game_, Choice> Evaluator<Game_, Choice> for RandomPlayout where Choice: Clone, Game_: Game<Choice> + Clone {
+    fn evaluate(&self, game_state: &Game_) -> Evaluation {
+        match self.max_length {
+            Some(max_length) => Evaluation::new(game_state.clone().random_play_limited(max_length)),
+            None => Evaluation::new(game_state.clone().random_play())
+        }
+    }
 
-        while choices.len() > 0 {
-            self.choose(&choices[rng.gen_range(0..choices.len())]);
+    fn evaluate_recorded(&self, game_state: &Game_, played: &mut Vec<(usize, Choice)>) -> Evaluation {
+        Evaluation::new(game_state.clone().playout(self.max_length, Some(played)))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Ongoing,
+    Win(usize),
+    Draw,
+    // the players from first place to last, players that tied share a place
+    Ranking(Vec<Vec<usize>>)
+}
+
+impl Outcome {
+    // the value each player gets: 1 for a win or an even split on a draw (or a game that hasn't ended), and for a
+    // ranking 1 for first place down to 0 for last, with tied players averaging the places they share
+    pub fn rewards(&self, players: usize) -> Vec<f64> {
+        match self {
+            Self::Win(winner) => {
+                let mut rewards = vec![0.0; players];
+                rewards[winner - 1] = 1.0;
+
+                rewards
+            },
+            Self::Ongoing | Self::Draw => vec![1.0 / players as f64; players],
+            Self::Ranking(places) => {
+                let mut rewards = vec![0.0; players];
+                let last_place = players.saturating_sub(1).max(1) as f64;
+                let mut position = 0;
+
+                for place in places {
+                    let average_position = position as f64 + place.len().saturating_sub(1) as f64 / 2.0;
+
+                    for &player in place {
+                        rewards[player - 1] = 1.0 - average_position / last_place;
+                    }
+
+                    position += place.len();
+                }
+
+                rewards
+            }
+        }
+    }
+
+    // the player won outright, nobody shares first place with them
+    pub fn is_win_for(&self, player: usize) -> bool {
+        match self {
+            Self::Win(winner) => *winner == player,
+            Self::Ranking(places) => places.first().is_some_and(|first| first.len() == 1 && first[0] == player),
+            _ => false
+        }
+    }
 
-            choices = self.get_choices();
+    // someone else finished ahead of the player
+    pub fn is_loss_for(&self, player: usize) -> bool {
+        match self {
+            Self::Win(winner) => *winner != player,
+            Self::Ranking(places) => !places.first().is_some_and(|first| first.contains(&player)),
+            _ => false
         }
+    }
+}
+
+// lets another thread end a search early, the search then returns the best choice it has found so far
+#[derive(Clone, Default)]
+pub struct StopHandle(Arc<AtomicBool>);
+
+impl StopHandle {
+    pub fn new() -> Self {
+        Self(Arc::new(AtomicBool::new(false)))
+    }
+
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// limits on a search, it ends as soon as any one of them is reached (with no limits it runs until stopped)
+#[derive(Clone, Default)]
+pub struct Budget {
+    pub cycles: Option<usize>,
+    pub time: Option<Duration>,
+    pub nodes: Option<usize>,
+    pub stop: Option<StopHandle>
+}
 
-        self.get_winner()
+impl Budget {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_cycles(mut self, cycles: usize) -> Self {
+        self.cycles = Some(cycles);
+
+        self
+    }
+
+    pub fn with_time(mut self, time: Duration) -> Self {
+        self.time = Some(time);
+
+        self
+    }
+
+    pub fn with_nodes(mut self, nodes: usize) -> Self {
+        self.nodes = Some(nodes);
+
+        self
+    }
+
+    pub fn with_stop(mut self, stop: StopHandle) -> Self {
+        self.stop = Some(stop);
+
+        self
+    }
+
+    fn is_exhausted(&self, cycles: usize, start: Instant, nodes: usize) -> bool {
+        self.cycles.is_some_and(|max_cycles| cycles >= max_cycles) ||
+        self.nodes.is_some_and(|max_nodes| nodes >= max_nodes) ||
+        self.time.is_some_and(|max_time| start.elapsed() >= max_time) ||
+        self.stop.as_ref().is_some_and(|stop| stop.is_stopped())
+    }
+}
+
+// how the search picks which child to go down. the child with the highest score is picked, ties at random.
+// visits include virtual loss, and closures with the same arguments are policies too
+pub trait SelectionPolicy {
+    fn score(&self, wins: f64, visits: f64, parent_visits: f64, prior: f64) -> f64;
+
+    // like `score` but also given the sum of the squared rewards, for policies that use the variance. `MCTS`
+    // calls this one
+    fn score_with_squares(&self, wins: f64, _squared_wins: f64, visits: f64, parent_visits: f64, prior: f64) -> f64 {
+        self.score(wins, visits, parent_visits, prior)
+    }
+}
+
+impl<F> SelectionPolicy for F where F: Fn(f64, f64, f64, f64) -> f64 {
+    fn score(&self, wins: f64, visits: f64, parent_visits: f64, prior: f64) -> f64 {
+        self(wins, visits, parent_visits, prior)
     }
 }
 
+// the variance aware policies (UCB1Tuned, UCBV) and KLUCB expect rewards between 0 and 1
 pub enum ExploitVsExplore {
     UCB1(f64),
+    // UCB1 with the exploration scaled by an upper bound on the variance of the rewards
+    UCB1Tuned,
+    // the exploration constant, which scales the term for the range of the rewards
+    UCBV(f64),
+    // the upper confidence bound of a bernoulli mean under the KL divergence, the constant (often 0 or 3) adds
+    // c * ln(ln(parent visits)) to the confidence level
+    KLUCB(f64),
+    // thompson sampling, each score is drawn from a beta posterior over the win rate (for win or loss games)
+    ThompsonBeta,
+    // thompson sampling with a gaussian posterior over the mean reward, for real valued or noisy rewards
+    ThompsonGaussian,
+    PUCT(f64),
     Random,
     ExploreFirst
 }
 
-impl ExploitVsExplore {
-    fn get_func(&self) -> Box<dyn Fn(f64, f64, f64) -> f64> {
+impl SelectionPolicy for ExploitVsExplore {
+    // without the squared rewards they're taken to be 0 or 1, which makes them the same as the rewards
+    fn score(&self, wins: f64, visits: f64, parent_visits: f64, prior: f64) -> f64 {
+        self.score_with_squares(wins, wins, visits, parent_visits, prior)
+    }
+
+    fn score_with_squares(&self, wins: f64, squared_wins: f64, visits: f64, parent_visits: f64, prior: f64) -> f64 {
+        let mean = wins / visits;
+        let variance = (squared_wins / visits - mean * mean).max(0.0);
+
         match self {
-            Self::UCB1(exploration_constant) => {
-                let c = *exploration_constant;
+            Self::UCB1(c) => mean + c * (parent_visits.ln() / visits).sqrt(),
+            Self::UCB1Tuned => {
+                let log_ratio = parent_visits.ln() / visits;
 
-                Box::new(move |wins, visits, parent_visits| {
-                    wins / visits + c * (parent_visits.ln() / visits)
-                })
+                mean + (log_ratio * (variance + (2.0 * log_ratio).sqrt()).min(0.25)).sqrt()
+            },
+            Self::UCBV(c) => {
+                let log_ratio = parent_visits.ln() / visits;
+
+                mean + (2.0 * variance * log_ratio).sqrt() + c * 3.0 * log_ratio
+            },
+            Self::KLUCB(c) => {
+                let log_visits = parent_visits.ln();
+
+                kl_ucb(mean, visits, log_visits + c * log_visits.ln().max(0.0))
+            },
+            // a uniform prior, so wins and losses are counted on top of one of each
+            Self::ThompsonBeta => sample_beta(1.0 + wins.max(0.0), 1.0 + (visits - wins).max(0.0), &mut rand::thread_rng()),
+            // one pseudo observation of 0.5 with the largest variance a reward between 0 and 1 can have (0.25)
+            // keeps the posterior wide until the child has a few visits
+            Self::ThompsonGaussian => {
+                let posterior_mean = (wins + 0.5) / (visits + 1.0);
+                let posterior_variance = (visits * variance + 0.25) / (visits + 1.0) / (visits + 1.0);
+
+                posterior_mean + posterior_variance.sqrt() * sample_standard_normal(&mut rand::thread_rng())
             },
-            Self::Random => Box::new(move |_, _, _| {
-                let mut rng = rand::thread_rng();
+            Self::PUCT(c) => mean + c * prior * parent_visits.sqrt() / (1.0 + visits),
+            Self::Random => rand::thread_rng().gen::<f64>(),
+            Self::ExploreFirst => 1.0 / visits
+        }
+    }
+}
+
+// the largest mean q with visits * kl(mean, q) <= bound, found by bisection
+fn kl_ucb(mean: f64, visits: f64, bound: f64) -> f64 {
+    if visits * bernoulli_kl(mean, 1.0) <= bound { return 1.0 };
+
+    let (mut low, mut high) = (mean.clamp(0.0, 1.0), 1.0);
+
+    for _ in 0..16 {
+        let q = (low + high) / 2.0;
+
+        if visits * bernoulli_kl(mean, q) > bound { high = q } else { low = q };
+    }
+
+    low
+}
+
+fn bernoulli_kl(p: f64, q: f64) -> f64 {
+    let p = p.clamp(1e-12, 1.0 - 1e-12);
+    let q = q.clamp(1e-12, 1.0 - 1e-12);
+
+    p * (p / q).ln() + (1.0 - p) * ((1.0 - p) / (1.0 - q)).ln()
+}
+
+// box-muller
+fn sample_standard_normal(rng: &mut impl Rng) -> f64 {
+    let u = 1.0 - rng.gen::<f64>();
+    let v = rng.gen::<f64>();
+
+    (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+}
+
+// marsaglia and tsang's method, shapes below 1 are boosted by one and scaled back down
+fn sample_gamma(shape: f64, rng: &mut impl Rng) -> f64 {
+    if shape < 1.0 {
+        return sample_gamma(shape + 1.0, rng) * (1.0 - rng.gen::<f64>()).powf(1.0 / shape);
+    }
+
+    let d = shape - 1.0 / 3.0;
+    let c = 1.0 / (9.0 * d).sqrt();
+
+    loop {
+        let x = sample_standard_normal(rng);
+        let v = (1.0 + c * x).powi(3);
+
+        if v <= 0.0 { continue };
 
-                rng.gen::<f64>()
-            }),
-            Self::ExploreFirst => Box::new(move |_, visits, _| 1.0 / visits)
+        let u = 1.0 - rng.gen::<f64>();
+
+        if u.ln() < 0.5 * x * x + d - d * v + d * v.ln() {
+            return d * v;
         }
     }
 }
 
+fn sample_beta(alpha: f64, beta: f64, rng: &mut impl Rng) -> f64 {
+    let x = sample_gamma(alpha, rng);
+    let y = sample_gamma(beta, rng);
+
+    x / (x + y)
+}
+
+// how the choice is made once the search is done. choices the solver has proven are weighed by their proven
+// reward instead, as the search stops visiting them
+pub enum FinalSelection {
+    // the highest win rate
+    Max,
+    // the most visits
+    Robust,
+    // the child with both the highest win rate and the most visits. when they're different children the search
+    // carries on for up to a tenth more cycles to settle it (within the rest of the budget), then the most visited
+    // is picked
+    MaxRobust,
+    // the highest lower confidence bound, win rate - a / sqrt(visits)
+    Secure(f64)
+}
+
 #[allow(dead_code)]
-pub struct MCTS<Game_, Choice> where Choice: Clone, Game_: Game<Choice> + Clone {
+pub struct MCTS<Game_, Choice, Eval = RandomPlayout, Policy = ExploitVsExplore> where Choice: Clone, Game_: Game<Choice> + Clone, Eval: Evaluator<Game_, Choice>, Policy: SelectionPolicy {
     players: usize,
-    exploit_vs_explore: ExploitVsExplore,
+    policy: Policy,
+    evaluator: Eval,
+    virtual_loss: f64,
+    // the rave equivalence parameter, None when rave is off
+    rave: Option<f64>,
+    // k and alpha for progressive widening, None to expand every choice at once
+    widening: Option<(f64, f64)>,
+    // walk one state through the tree with `Undo::undo` rather than keeping a state per node
+    undo: Option<fn(&mut Game_, &Choice)>,
+    final_selection: FinalSelection,
     __anoying: (PhantomData<Choice>, PhantomData<Game_>)
 }
 
+// each search has a table of its own, nodes only keep what the table held once the search is over
+type Transpositions = Mutex<HashMap<u64, Transposition>>;
+
+// the statistics shared by every node with the same key
+struct Transposition {
+    wins: Vec<f64>,
+    squared_wins: Vec<f64>,
+    visits: f64
+}
+
+impl<Game_, Choice, Policy> MCTS<Game_, Choice, RandomPlayout, Policy> where Choice: Clone, Game_: Game<Choice> + Clone, Policy: SelectionPolicy {
+    pub fn new(initial_game_state: &Game_, policy: Policy) -> Self {
+        Self::with_evaluator(initial_game_state, policy, RandomPlayout::new())
+    }
+}
+
+impl<Game_, Choice, Eval, Policy> MCTS<Game_, Choice, Eval, Policy> where Choice: Clone, Game_: Undo<Choice> + Clone, Eval: Evaluator<Game_, Choice>, Policy: SelectionPolicy {
+    // makes the search hand one state down the tree with `choose` and back up with `Undo::undo`, so only the root
+    // (the tree passed to `advise_with_tree` included) keeps a game state. for games with big states
+    pub fn with_undo(mut self) -> Self {
+        self.undo = Some(<Game_ as Undo<Choice>>::undo);
+
+        self
+    }
+}
+
 #[allow(dead_code)]
-impl<Game_, Choice> MCTS<Game_, Choice> where Choice: Clone, Game_: Game<Choice> + Clone {
-    pub fn new(initial_game_state: &Game_, exploit_vs_explore: ExploitVsExplore) -> Self {
+impl<Game_, Choice, Eval, Policy> MCTS<Game_, Choice, Eval, Policy> where Choice: Clone, Game_: Game<Choice> + Clone, Eval: Evaluator<Game_, Choice>, Policy: SelectionPolicy {
+    pub fn with_evaluator(initial_game_state: &Game_, policy: Policy, evaluator: Eval) -> Self {
         let players = initial_game_state.get_num_players();
 
-        Self { players, exploit_vs_explore, __anoying: (PhantomData, PhantomData) }
+        Self { players, policy, evaluator, virtual_loss: 1.0, rave: None, widening: None, undo: None, final_selection: FinalSelection::Max, __anoying: (PhantomData, PhantomData) }
     }
-    
-    // returns the winner
-    fn mcts(&self, node: &mut Node<Game_, Choice>) -> usize {
-        if node.visits < 1.0 {
-            let winner = node.game_state.clone().random_play();
-
-            return node.update(winner, self.players);
-        } else if node.visits < 2.0 {
-            node.next = node.game_state.get_choices().iter().map(|choice| {
-                let mut next_game_state = node.game_state.clone();
-                next_game_state.choose(choice);
 
-                Node::new(next_game_state, Some(choice.clone()), self.players)
-            }).collect();
+    // how many lost visits a worker adds to each node on its path while its evaluation is pending
+    pub fn with_virtual_loss(mut self, virtual_loss: f64) -> Self {
+        self.virtual_loss = virtual_loss;
 
-            if node.next.len() == 0 {
-                node.winner = Some(node.game_state.get_winner());
-            }
-        }
+        self
+    }
 
-        if let Some(winner) = node.winner {
-            return node.update(winner, self.players);
-        }
+    // turns on rapid action value estimation: each choice also keeps all-moves-as-first statistics (from every
+    // cycle where its player made that choice later on, in the tree or the playout) which are mixed into its value
+    // with the weight sqrt(k / (3 * visits + k)). k is the number of visits at which both count equally, a few
+    // hundred to a few thousand is typical. the choices are recorded by `Evaluator::evaluate_recorded`
+    // (`Game::playout` for the default evaluator)
+    pub fn with_rave(mut self, k: f64) -> Self {
+        self.rave = Some(k);
 
-        let next= node.best_next_index(
-            node.game_state.get_turn(), 
-            self.exploit_vs_explore.get_func()
-        ).expect("Tried to branch on dead end node");
+        self
+    }
 
-        let winner = self.mcts(&mut node.next[next]);
+    // turns on progressive widening: rather than expanding every choice, a node gets choices from
+    // `Game::sample_choice` one at a time until it has k * visits^alpha children (alpha is usually 0.25 to 0.5).
+    // widened children all get a prior of 1, and a node with choices left unsampled can only be proven a win
+    pub fn with_progressive_widening(mut self, k: f64, alpha: f64) -> Self {
+        self.widening = Some((k, alpha));
 
-        node.update(winner, self.players)
+        self
     }
 
-    pub fn advise(&self, game_state: &Game_, cycles: usize) -> Option<Choice> where Choice: PartialEq {
-        let mut base_node = Node::default(game_state.clone(), self.players);
+    // how the choice is picked after the search (the highest win rate by default)
+    pub fn with_final_selection(mut self, final_selection: FinalSelection) -> Self {
+        self.final_selection = final_selection;
 
-        self.advise_with_tree(&mut base_node, cycles)
+        self
     }
 
-    pub fn advise_with_tree(&self, tree: &mut Node<Game_, Choice>, cycles: usize) -> Option<Choice> where Choice: PartialEq {
-        for _ in 0..cycles {
-            self.mcts(tree);
-        }
+    // copies the shared statistics into each child that has been reached some other way (children whose state
+    // hasn't been created yet have no key, so they only pick them up once they're first selected)
+    fn sync_transpositions(&self, node: &mut Node<Game_, Choice>, transpositions: &Transpositions) {
+        let transpositions = transpositions.lock().unwrap();
 
-        match tree.best_next_index(
-            tree.game_state.get_turn(), 
-            Box::new(|wins, visits, _| wins / visits)
-        ) {
-            None => None,
-            Some(index) => tree.next[index].choice.clone()
+        for next in node.next.iter_mut() {
+            if let Some(transposition) = next.key.and_then(|key| transpositions.get(&key)) {
+                next.wins.clone_from(&transposition.wins);
+                next.squared_wins.clone_from(&transposition.squared_wins);
+                next.visits = transposition.visits;
+            }
         }
     }
-}
 
-pub struct Node<Game_, Choice> where Game_: Game<Choice> + Clone, Choice: Clone {
-    winner: Option<usize>,
-    pub game_state: Game_,
-    choice: Option<Choice>,
-    wins: Vec<f64>,
-    visits: f64,
-    next: Vec<Node<Game_, Choice>>
-}
+    // a position new to the table starts from the node's own statistics, so a tree searched before keeps what it
+    // had learnt
+    fn update(&self, node: &mut Node<Game_, Choice>, transpositions: &Transpositions, values: Vec<f64>) -> Vec<f64> {
+        let Some(key) = node.key else { return node.update(values) };
 
-impl<Game_, Choice> Node<Game_, Choice> where Game_: Game<Choice> + Clone, Choice: Clone {
-    fn new(game_state: Game_, choice: Option<Choice>, players: usize) -> Self {
-        Node { winner: None, game_state, choice, wins: vec![0.0; players], visits: 0.0, next: Vec::new() }
-    }
+        let mut transpositions = transpositions.lock().unwrap();
+        let transposition = transpositions.entry(key).or_insert_with(|| Transposition { 
+            wins: node.wins.clone(), 
+            squared_wins: node.squared_wins.clone(), 
+            visits: node.visits 
+        });
 
-    pub fn default(game_state: Game_, players: usize) -> Self {
-        Node { winner: None, game_state, choice: None, wins: vec![0.0; players], visits: 0.0, next: Vec::new() }
+        transposition.wins.iter_mut().zip(values.iter()).for_each(|(win, value)| *win += value);
+        transposition.squared_wins.iter_mut().zip(values.iter()).for_each(|(win, value)| *win += value * value);
+        transposition.visits += 1.0;
+
+        node.wins.clone_from(&transposition.wins);
+        node.squared_wins.clone_from(&transposition.squared_wins);
+        node.visits = transposition.visits;
+
+        values
     }
 
-    fn best_next_index(&self, player_id: usize, evaluator: Box<dyn Fn(f64, f64, f64)-> f64>) -> Option<usize> {
-        if self.next.len() == 0 { return None };
+    // returns the number of nodes created. a widening node gets more children as its visits grow
+    fn expand(&self, node: &mut Node<Game_, Choice>) -> usize where Choice: PartialEq {
+        if node.partial { return self.widen(node) };
+
+        let outcome = node.state().get_outcome();
+
+        if outcome != Outcome::Ongoing {
+            node.rewards = Some(node.state().get_rewards());
+            node.outcome = Some(outcome);
+
+            return 0;
+        }
+
+        node.turn = node.state().get_turn();
+
+        // chance outcomes are stored like choices, with their probability as the prior
+        let (choices, priors) = match (node.state().get_chance_outcomes(), self.widening) {
+            (Some(outcomes), _) => {
+                let total: f64 = outcomes.iter().map(|(_, weight)| weight).sum();
+                node.chance = true;
 
-        let mut best = (Vec::new(), -1.0);
+                outcomes.into_iter().map(|(outcome, weight)| (outcome, weight / total)).unzip()
+            },
+            (None, Some(_)) => {
+                node.partial = true;
 
-        for i in 0..self.next.len() {
-            let score = evaluator(self.next[i].wins[player_id - 1], self.next[i].visits + 0.00001, self.visits);
+                (Vec::new(), Vec::new())
+            },
+            (None, None) => {
+                let choices = node.state().get_choices();
+                let priors = match node.priors.take() {
+                    Some(priors) => priors,
+                    None => node.state().get_priors(&choices)
+                };
 
-            if score > best.1 {
-                best = (vec![i], score);
-            } else if score == best.1 {
-                best.0.push(i);
+                (choices, priors)
             }
         };
-        
-        let mut rng = rand::thread_rng();
 
-        Some(best.0[rng.gen_range(0..best.0.len())])
-    }
+        assert_eq!(choices.len(), priors.len(), "The priors don't match the choices");
 
-    fn update(&mut self, winner: usize, players: usize) -> usize {
-        let bonus = if let Some(_) = self.winner { 1.0 } else { 1.0 };
+        // the children's states are only created once they're selected
+        node.next = choices.into_iter().zip(priors).map(|(choice, prior)| Node::new(Some(choice), prior, self.players)).collect();
 
-        if winner > 0 { 
-            self.wins[winner - 1] += bonus;
-        } else { 
-            self.wins.iter_mut().for_each(|win| *win += bonus / (players as f64)) 
+        if node.partial {
+            self.widen(node);
         }
 
-        self.visits += bonus;
+        // nobody can move but the game hasn't been decided, so it's a draw
+        if node.next.is_empty() {
+            node.outcome = Some(Outcome::Draw);
+            node.rewards = Some(node.state().get_rewards());
+        }
 
-        winner
+        node.next.len()
     }
 
-    pub fn choose(&mut self, choice: &Choice) where Choice: PartialEq {
-        let chosen_node_index = self.next
-            .iter()
-            .position(|node| {
-                if let Some(node_choice) = node.choice.clone() { &node_choice == choice } else { false }
-            }).expect("The node does not include this choice");
+    // samples new children until there are k * visits^alpha of them, giving up until the next visit when a
+    // sample repeats a choice that's already there
+    fn widen(&self, node: &mut Node<Game_, Choice>) -> usize where Choice: PartialEq {
+        let Some((k, alpha)) = self.widening else { return 0 };
 
-        *self = self.next.remove(chosen_node_index);
-    }
-}
+        let allowed = (k * node.visits.max(1.0).powf(alpha)).ceil() as usize;
+        let mut rng = rand::thread_rng();
+        let mut created = 0;
 
-impl<Game_, Choice> std::fmt::Display for Node<Game_, Choice> where Game_: Game<Choice> + Clone, Choice: Clone + std::fmt::Debug {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if let Some(winner) = self.winner { 
-            return write!(f, "{{\"choice\": \"{:?}\",  \"winner\": {}, \"visits\": {}}}", self.choice, winner, self.visits);
-        } else if self.next.len() == 0 && self.visits == 0.0 {
-            return write!(f, "{{}}");
-        } else if self.next.len() == 0 {
-            return write!(f, "{{\"choice\": \"{:?}\",  \"wins\": {:?}, \"visits\": {}}}", self.choice, self.wins, self.visits);
-        }
+        while node.next.len() < allowed {
+            let Some(choice) = node.state().sample_choice(&mut rng) else { break };
 
-        let mut str = format!("[{}", self.next[0]);
+            if node.next.iter().any(|next| next.choice.as_ref() == Some(&choice)) { break };
+
+            node.next.push(Node::new(Some(choice), 1.0, self.players));
+            created += 1;
+        }
 
-        for node in self.next[1..].iter() {
-            str += format!(", {}", node).as_str();
+        created
+    }
+    
+    // chance nodes draw an outcome by probability, otherwise the policy picks among the unsolved children (or
+    // among all of them at a widening node, which stays unsolved while its sampled children are all solved)
+    fn select(&self, node: &Node<Game_, Choice>) -> usize {
+        if node.chance {
+            return sample_weighted(node.next.iter().map(|next| next.prior), &mut rand::thread_rng());
         }
 
-        str += "]";
+        let player = node.state().get_turn();
 
-        write!(f, "{{\"choice\": \"{:?}\", \"wins\": {:?}, \"visits\": {}, \"next\": {}}}", self.choice, self.wins, self.visits, str)
+        node.best_next_index(player, &self.policy, self.rave, |next| next.outcome.is_none())
+            .or_else(|| if node.partial { node.best_next_index(player, &self.policy, self.rave, |_| true) } else { None })
+            .expect("Tried to branch on dead end node")
     }
-}
\ No newline at end of file
+
+    fn evaluate(&self, game_state: &Game_, played: &mut Vec<(usize, Choice)>) -> Evaluation {
+        if self.rave.is_some() {
+            self.evaluator.evaluate_recorded(game_state, played)
+        } else {
+            self.evaluator.evaluate(game_state)
+        }
+    }
+
+    // one cycle of the search: walks down to a leaf, evaluates it and backs the values up. the path is kept in an
+    // explicit buffer rather than on the call stack, so very deep trees can't overflow it
+    fn cycle(&self, tree: &mut Node<Game_, Choice>, transpositions: &Transpositions, path: &mut Vec<usize>, played: &mut Vec<(usize, Choice)>, nodes: &mut usize) where Choice: PartialEq {
+        path.clear();
+        played.clear();
+
+        let evaluation = match self.descend(tree, transpositions, path, nodes, 0.0) {
+            Leaf::Unvisited => self.evaluate(Self::node_at(tree, path).state(), played),
+            Leaf::Terminal(values) => Evaluation::new(values)
+        };
+
+        if let Some(undo) = self.undo {
+            Self::return_state(tree, path, undo);
+        }
+
+        self.backup(tree, transpositions, path, evaluation, played, 0.0);
+    }
+
+    // the choice for the player to move, None once the game is over. every `advise` also returns None when chance
+    // is to move, as there's no choice to make, so the caller applies an outcome from `Game::get_chance_outcomes`
+    // itself (rather than taking None as the end of the game)
+    pub fn advise(&self, game_state: &Game_, cycles: usize) -> Option<Choice> where Choice: PartialEq {
+        let mut base_node = Node::default(game_state.clone(), self.players);
+
+        self.advise_with_tree(&mut base_node, cycles)
+    }
+
+    pub fn advise_with_tree(&self, tree: &mut Node<Game_, Choice>, cycles: usize) -> Option<Choice> where Choice: PartialEq {
+        self.advise_with_tree_budget(tree, &Budget::new().with_cycles(cycles))
+    }
+
+    pub fn advise_with_budget(&self, game_state: &Game_, budget: &Budget) -> Option<Choice> where Choice: PartialEq {
+        let mut base_node = Node::default(game_state.clone(), self.players);
+
+        self.advise_with_tree_budget(&mut base_node, budget)
+    }
+
+    pub fn advise_with_tree_budget(&self, tree: &mut Node<Game_, Choice>, budget: &Budget) -> Option<Choice> where Choice: PartialEq {
+        self.search(tree, budget);
+
+        self.best_choice(tree)
+    }
+
+    // samples the choice in proportion to visits^(1 / temperature) rather than picking the best one, for varied
+    // play (self play games, openings, weaker bots). a temperature of 1 follows the visits, lower temperatures
+    // get closer to always picking the most visited child (which a temperature of 0 does)
+    pub fn advise_sampled(&self, game_state: &Game_, cycles: usize, temperature: f64) -> Option<Choice> where Choice: PartialEq {
+        let mut base_node = Node::default(game_state.clone(), self.players);
+
+        self.advise_with_tree_sampled(&mut base_node, &Budget::new().with_cycles(cycles), temperature)
+    }
+
+    pub fn advise_with_tree_sampled(&self, tree: &mut Node<Game_, Choice>, budget: &Budget, temperature: f64) -> Option<Choice> where Choice: PartialEq {
+        self.search(tree, budget);
+
+        self.sampled_choice(tree, temperature)
+    }
+
+    // the entries in the transposition table count towards the node cap along with the tree
+    fn search(&self, tree: &mut Node<Game_, Choice>, budget: &Budget) where Choice: PartialEq {
+        let start = Instant::now();
+        let transpositions = Mutex::new(HashMap::new());
+        let mut nodes = tree.size();
+        let mut cycles = 0;
+        let mut path = Vec::new();
+        let mut played = Vec::new();
+        let size = |nodes: usize| nodes + transpositions.lock().unwrap().len();
+
+        // a solved root has nothing left to search
+        while tree.outcome.is_none() && !budget.is_exhausted(cycles, start, size(nodes)) {
+            self.cycle(tree, &transpositions, &mut path, &mut played, &mut nodes);
+            cycles += 1;
+        }
+
+        // only the cycle limit is relaxed, the time, nodes and stop handle still end the extra cycles
+        if let FinalSelection::MaxRobust = self.final_selection {
+            let extended = Budget { cycles: Some(cycles + cycles / 10), ..budget.clone() };
+
+            while tree.outcome.is_none() && !extended.is_exhausted(cycles, start, size(nodes)) && !self.max_is_robust(tree) {
+                self.cycle(tree, &transpositions, &mut path, &mut played, &mut nodes);
+                cycles += 1;
+            }
+        }
+    }
+
+    // walks down to the node to evaluate, expanding nodes on the way (counted in `nodes`) and recording the path
+    // taken so the evaluation can be backed up once it's done. parallel searches add virtual loss to every node
+    // on the path
+    fn descend(&self, tree: &mut Node<Game_, Choice>, transpositions: &Transpositions, path: &mut Vec<usize>, nodes: &mut usize, virtual_loss: f64) -> Leaf where Choice: PartialEq {
+        let mut node = tree;
+
+        loop {
+            node.virtual_loss += virtual_loss;
+
+            if node.visits < 1.0 {
+                return Leaf::Unvisited;
+            } else if (node.next.is_empty() || node.partial) && node.outcome.is_none() {
+                *nodes += self.expand(node);
+            }
+
+            if let Some(rewards) = &node.rewards {
+                return Leaf::Terminal(rewards.clone());
+            }
+
+            if node.key.is_some() {
+                self.sync_transpositions(node, transpositions);
+            }
+
+            let next = self.select(node);
+
+            if self.undo.is_some() {
+                node.lend_state(next);
+            } else {
+                node.create_next_state(next);
+            }
+
+            path.push(next);
+            node = &mut node.next[next];
+        }
+    }
+
+    // `played` holds the choices made by the evaluation, for rave
+    fn backup(&self, tree: &mut Node<Game_, Choice>, transpositions: &Transpositions, path: &[usize], evaluation: Evaluation, played: &[(usize, Choice)], virtual_loss: f64) where Choice: PartialEq {
+        // the choice made at each node on the path (None at chance nodes)
+        let mut moves = Vec::new();
+
+        if self.rave.is_some() {
+            let mut node = &*tree;
+
+            for &index in path {
+                moves.push(if node.chance { None } else { Some((node.turn, node.next[index].choice.clone().unwrap())) });
+                node = &node.next[index];
+            }
+        }
+
+        let mut node = &mut *tree;
+
+        for (depth, &index) in path.iter().enumerate() {
+            node.virtual_loss -= virtual_loss;
+            self.update(node, transpositions, evaluation.values.clone());
+
+            if self.rave.is_some() && !node.chance {
+                node.update_amaf(&evaluation.values, moves[depth..].iter().flatten().chain(played.iter()));
+            }
+
+            node = &mut node.next[index];
+        }
+
+        if node.visits < 1.0 {
+            node.priors = evaluation.priors;
+        }
+
+        node.virtual_loss -= virtual_loss;
+        self.update(node, transpositions, evaluation.values);
+
+        // walk back up for as long as the solved results keep proving the parents
+        let mut depth = path.len();
+
+        while depth > 0 && Self::node_at(tree, &path[..depth]).outcome.is_some() {
+            depth -= 1;
+            Self::node_at(tree, &path[..depth]).prove();
+        }
+    }
+
+    // hands the state lent down the path back up to the root, undoing the choices from the bottom up
+    fn return_state(tree: &mut Node<Game_, Choice>, path: &[usize], undo: fn(&mut Game_, &Choice)) {
+        let mut choices = Vec::with_capacity(path.len());
+        let mut node = &mut *tree;
+
+        for &index in path {
+            choices.push(node.next[index].choice.clone().unwrap());
+            node = &mut node.next[index];
+        }
+
+        let mut game_state = node.game_state.take().expect("The leaf doesn't have the lent game state");
+
+        for choice in choices.iter().rev() {
+            undo(&mut game_state, choice);
+        }
+
+        tree.game_state = Some(game_state);
+    }
+
+    fn node_at<'a>(tree: &'a mut Node<Game_, Choice>, path: &[usize]) -> &'a mut Node<Game_, Choice> {
+        path.iter().fold(tree, |node, &index| &mut node.next[index])
+    }
+
+    pub fn advise_parallel(&self, game_state: &Game_, cycles: usize, threads: usize) -> Option<Choice> 
+    where Choice: PartialEq + Send + Sync, Game_: Send + Sync, Eval: Sync, Policy: Sync {
+        let mut base_node = Node::default(game_state.clone(), self.players);
+
+        self.advise_with_tree_parallel(&mut base_node, cycles, threads)
+    }
+
+    // runs the cycles on several threads sharing one tree, the tree is only locked while walking it so
+    // the evaluations themselves run in parallel
+    pub fn advise_with_tree_parallel(&self, tree: &mut Node<Game_, Choice>, cycles: usize, threads: usize) -> Option<Choice> 
+    where Choice: PartialEq + Send + Sync, Game_: Send + Sync, Eval: Sync, Policy: Sync {
+        let shared_tree = Mutex::new(&mut *tree);
+        let transpositions = Mutex::new(HashMap::new());
+        let started = AtomicUsize::new(0);
+
+        std::thread::scope(|scope| {
+            for _ in 0..threads.max(1) {
+                scope.spawn(|| {
+                    let mut path = Vec::new();
+                    let mut played = Vec::new();
+                    let mut nodes = 0;
+
+                    while started.fetch_add(1, Ordering::Relaxed) < cycles {
+                        path.clear();
+                        played.clear();
+
+                        let game_state = {
+                            let mut tree = shared_tree.lock().unwrap();
+
+                            if tree.outcome.is_some() { break };
+
+                            let leaf = self.descend(&mut tree, &transpositions, &mut path, &mut nodes, self.virtual_loss);
+                            let game_state = match leaf {
+                                Leaf::Unvisited => Some(Self::node_at(&mut tree, &path).state().clone()),
+                                Leaf::Terminal(_) => None
+                            };
+
+                            if let Some(undo) = self.undo {
+                                Self::return_state(&mut tree, &path, undo);
+                            }
+
+                            match leaf {
+                                Leaf::Unvisited => game_state.unwrap(),
+                                // backed up before unlocking, so no other worker sees a solved child 
+                                // whose parent hasn't been proven yet
+                                Leaf::Terminal(values) => {
+                                    self.backup(&mut tree, &transpositions, &path, Evaluation::new(values), &played, self.virtual_loss);
+
+                                    continue;
+                                }
+                            }
+                        };
+                        let evaluation = self.evaluate(&game_state, &mut played);
+
+                        self.backup(&mut shared_tree.lock().unwrap(), &transpositions, &path, evaluation, &played, self.virtual_loss);
+                    }
+                });
+            }
+        });
+
+        self.best_choice(tree)
+    }
+
+    // searches independent trees on separate threads (each using its own thread local rng and transposition
+    // table) and merges them before choosing, so the threads never wait on each other
+    pub fn advise_root_parallel(&self, game_state: &Game_, cycles: usize, threads: usize) -> Option<Choice> 
+    where Choice: PartialEq + Send + Sync, Game_: Send + Sync, Eval: Sync, Policy: Sync {
+        let threads = threads.max(1);
+
+        let trees: Vec<Node<Game_, Choice>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..threads).map(|thread| {
+                let thread_cycles = cycles / threads + if thread < cycles % threads { 1 } else { 0 };
+                let game_state = game_state.clone();
+
+                scope.spawn(move || {
+                    let mut tree = Node::default(game_state, self.players);
+                    // sharing one table would give every tree the same totals for its keyed nodes, which
+                    // merging would then add up again
+                    let transpositions = Mutex::new(HashMap::new());
+
+                    let mut nodes = 0;
+                    let mut path = Vec::new();
+                    let mut played = Vec::new();
+
+                    for _ in 0..thread_cycles {
+                        if tree.outcome.is_some() { break };
+
+                        self.cycle(&mut tree, &transpositions, &mut path, &mut played, &mut nodes);
+                    }
+
+                    tree
+                })
+            }).collect();
+
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+
+        let mut trees = trees.into_iter();
+        let mut merged = trees.next().unwrap();
+
+        for tree in trees {
+            merged.merge(tree);
+        }
+
+        self.best_choice(&merged)
+    }
+
+    // a proven win is played straight away, otherwise the final selection picks among the choices that aren't
+    // proven losses
+    fn best_choice(&self, tree: &Node<Game_, Choice>) -> Option<Choice> {
+        // nobody gets to choose the outcome of a chance node
+        if tree.chance { return None };
+
+        let player = tree.state().get_turn();
+
+        if let Some(winning) = tree.next.iter()
+            .filter(|next| next.outcome.as_ref().is_some_and(|outcome| outcome.is_win_for(player)))
+            .max_by(|a, b| a.reward(player).total_cmp(&b.reward(player))) {
+            return winning.choice.clone();
+        }
+
+        let not_lost = |next: &Node<Game_, Choice>| !next.outcome.as_ref().is_some_and(|outcome| outcome.is_loss_for(player));
+        let index = self.final_index(tree, player, not_lost).or_else(|| self.final_index(tree, player, |_| true));
+
+        match index {
+            None => None,
+            Some(index) => tree.next[index].choice.clone()
+        }
+    }
+
+    // like `best_choice`, a proven win is still played straight away, proven losses are avoided and the other
+    // solved children are weighed by their proven reward
+    fn sampled_choice(&self, tree: &Node<Game_, Choice>, temperature: f64) -> Option<Choice> {
+        if tree.chance { return None };
+
+        let player = tree.state().get_turn();
+
+        if tree.next.iter().any(|next| next.outcome.as_ref().is_some_and(|outcome| outcome.is_win_for(player))) {
+            return self.best_choice(tree);
+        }
+
+        let not_lost = |next: &Node<Game_, Choice>| !next.outcome.as_ref().is_some_and(|outcome| outcome.is_loss_for(player));
+        let any_not_lost = tree.next.iter().any(not_lost);
+        let include = |next: &Node<Game_, Choice>| !any_not_lost || not_lost(next);
+
+        // each candidate with the visits it's sampled by
+        let mut candidates: Vec<(&Node<Game_, Choice>, f64)> = tree.next.iter()
+            .filter(|next| next.outcome.is_none() && include(next))
+            .map(|next| (next, next.visits))
+            .collect();
+
+        // the solver stops visiting solved children, so the best of them is sampled by value instead: it replaces
+        // the unsolved children whose win rate it's proven to be at least as good as, with the most visits of those
+        if let Some(solved) = Self::best_solved(tree, player, include) {
+            let reward = tree.next[solved].reward(player);
+            let beaten = |next: &Node<Game_, Choice>| next.wins[player - 1] / (next.visits + 0.00001) <= reward;
+            let visits = candidates.iter().filter(|(next, _)| beaten(next)).map(|(_, visits)| *visits).fold(0.0, f64::max);
+
+            candidates.retain(|(next, _)| !beaten(next));
+            candidates.push((&tree.next[solved], visits));
+        }
+
+        let most_visited = candidates.iter().map(|(_, visits)| *visits).fold(0.0, f64::max);
+
+        if temperature <= 0.0 || most_visited <= 0.0 {
+            return candidates.iter().max_by(|a, b| a.1.total_cmp(&b.1))?.0.choice.clone();
+        }
+
+        // scaled by the most visits first so the powers can't overflow
+        let weights = candidates.iter().map(|(_, visits)| (visits / most_visited).powf(1.0 / temperature));
+
+        candidates[sample_weighted(weights, &mut rand::thread_rng())].0.choice.clone()
+    }
+
+    // the final selection picks among the unsolved children, and its pick is then weighed against the best solved
+    // child: the pick's win rate (its lower bound for `Secure`) against the solved child's proven reward. the
+    // solver stops visiting solved children, so their visits and win rates say little about them
+    fn final_index(&self, tree: &Node<Game_, Choice>, player: usize, include: impl Fn(&Node<Game_, Choice>) -> bool) -> Option<usize> {
+        let unsolved = |next: &Node<Game_, Choice>| next.outcome.is_none() && include(next);
+
+        let index = match self.final_selection {
+            FinalSelection::Max => tree.best_next_index(player, &|wins: f64, visits: f64, _, _| wins / visits, None, unsolved),
+            FinalSelection::Robust => tree.best_next_index(player, &|_, visits: f64, _, _| visits, None, unsolved),
+            FinalSelection::MaxRobust => Self::max_and_robust(tree, player, unsolved)
+                .map(|(max, robust)| if tree.next[max].visits >= tree.next[robust].visits { max } else { robust }),
+            FinalSelection::Secure(a) => tree.best_next_index(player, &|wins: f64, visits: f64, _, _| wins / visits - a / visits.sqrt(), None, unsolved)
+        };
+
+        match (index, Self::best_solved(tree, player, &include)) {
+            (Some(index), Some(solved)) => Some(if self.final_value(&tree.next[index], player) > tree.next[solved].reward(player) { index } else { solved }),
+            (index, solved) => index.or(solved)
+        }
+    }
+
+    // what an unsolved child is expected to be worth, to compare it with a proven reward
+    fn final_value(&self, next: &Node<Game_, Choice>, player: usize) -> f64 {
+        let visits = next.visits + 0.00001;
+        let mean = next.wins[player - 1] / visits;
+
+        match self.final_selection {
+            FinalSelection::Secure(a) => mean - a / visits.sqrt(),
+            _ => mean
+        }
+    }
+
+    // the solved child with the highest proven reward
+    fn best_solved(tree: &Node<Game_, Choice>, player: usize, include: impl Fn(&Node<Game_, Choice>) -> bool) -> Option<usize> {
+        tree.next.iter().enumerate()
+            .filter(|(_, next)| next.outcome.is_some() && include(next))
+            .max_by(|(_, a), (_, b)| a.reward(player).total_cmp(&b.reward(player)))
+            .map(|(index, _)| index)
+    }
+
+    // the children with the highest win rate and the most visits
+    fn max_and_robust(tree: &Node<Game_, Choice>, player: usize, include: impl Fn(&Node<Game_, Choice>) -> bool) -> Option<(usize, usize)> {
+        let max = tree.best_next_index(player, &|wins: f64, visits: f64, _, _| wins / visits, None, &include)?;
+        let robust = tree.best_next_index(player, &|_, visits: f64, _, _| visits, None, &include)?;
+
+        Some((max, robust))
+    }
+
+    // among the unsolved choices, the one with the highest win rate is also (one of) the most visited, or a solved
+    // choice that isn't a loss is proven to be worth at least as much as both of them, or there's nothing to choose
+    fn max_is_robust(&self, tree: &Node<Game_, Choice>) -> bool {
+        if tree.chance || tree.next.is_empty() { return true };
+
+        let player = tree.state().get_turn();
+        let not_lost = |next: &Node<Game_, Choice>| !next.outcome.as_ref().is_some_and(|outcome| outcome.is_loss_for(player));
+        let Some((max, robust)) = Self::max_and_robust(tree, player, |next| next.outcome.is_none()) else { return true };
+
+        tree.next[max].visits >= tree.next[robust].visits || Self::best_solved(tree, player, not_lost).is_some_and(|solved| {
+            let reward = tree.next[solved].reward(player);
+
+            reward >= self.final_value(&tree.next[max], player) && reward >= self.final_value(&tree.next[robust], player)
+        })
+    }
+}
+
+enum Leaf {
+    Unvisited,
+    Terminal(Vec<f64>)
+}
+
+pub struct Node<Game_, Choice> where Game_: Game<Choice> + Clone, Choice: Clone {
+    // the proven outcome of a solved node
+    outcome: Option<Outcome>,
+    // what a solved node backs up on every visit
+    rewards: Option<Vec<f64>>,
+    chance: bool,
+    // the children are a sample of the choices, see `MCTS::with_progressive_widening`
+    partial: bool,
+    // the player to move, kept once the node is expanded so it's known without the state
+    turn: usize,
+    key: Option<u64>,
+    // None until the node is first selected
+    game_state: Option<Game_>,
+    choice: Option<Choice>,
+    prior: f64,
+    priors: Option<Vec<f64>>,
+    wins: Vec<f64>,
+    // the sum of the squared values, for the variance aware policies
+    squared_wins: Vec<f64>,
+    visits: f64,
+    // all-moves-as-first statistics for the choice leading here, used by rave
+    amaf_wins: Vec<f64>,
+    amaf_visits: f64,
+    virtual_loss: f64,
+    next: Vec<Node<Game_, Choice>>
+}
+
+impl<Game_, Choice> Node<Game_, Choice> where Game_: Game<Choice> + Clone, Choice: Clone {
+    fn new(choice: Option<Choice>, prior: f64, players: usize) -> Self {
+        Node { outcome: None, rewards: None, chance: false, partial: false, turn: 0, key: None, game_state: None, choice, prior, priors: None, wins: vec![0.0; players], squared_wins: vec![0.0; players], visits: 0.0, amaf_wins: vec![0.0; players], amaf_visits: 0.0, virtual_loss: 0.0, next: Vec::new() }
+    }
+
+    pub fn default(game_state: Game_, players: usize) -> Self {
+        Node { outcome: None, rewards: None, chance: false, partial: false, turn: 0, key: game_state.get_key(), game_state: Some(game_state), choice: None, prior: 1.0, priors: None, wins: vec![0.0; players], squared_wins: vec![0.0; players], visits: 0.0, amaf_wins: vec![0.0; players], amaf_visits: 0.0, virtual_loss: 0.0, next: Vec::new() }
+    }
+
+    // the position at this node, None for a child that hasn't been selected yet
+    pub fn game_state(&self) -> Option<&Game_> {
+        self.game_state.as_ref()
+    }
+
+    fn state(&self) -> &Game_ {
+        self.game_state.as_ref().expect("The node's game state hasn't been created")
+    }
+
+    // plays the choice of a child to create its state, if that hasn't been done already
+    fn create_next_state(&mut self, index: usize) {
+        if self.next[index].game_state.is_some() { return };
+
+        let mut game_state = self.state().clone();
+        game_state.choose(self.next[index].choice.as_ref().unwrap());
+
+        self.next[index].key = game_state.get_key();
+        self.next[index].game_state = Some(game_state);
+    }
+
+    // moves the state down to a child by playing its choice, see `MCTS::with_undo`
+    fn lend_state(&mut self, index: usize) {
+        let mut game_state = self.game_state.take().expect("The node's game state hasn't been created");
+        game_state.choose(self.next[index].choice.as_ref().unwrap());
+
+        self.next[index].key = game_state.get_key();
+        self.next[index].game_state = Some(game_state);
+    }
+
+    // with `rave` set the win rate passed to the policy is mixed with the all-moves-as-first win rate
+    fn best_next_index(&self, player_id: usize, policy: &impl SelectionPolicy, rave: Option<f64>, include: impl Fn(&Self) -> bool) -> Option<usize> {
+        let scores = self.next.iter().enumerate().filter(|(_, next)| include(next)).map(|(i, next)| {
+            let visits = next.visits + next.virtual_loss + 0.00001;
+            let wins = match rave {
+                Some(k) if next.amaf_visits > 0.0 => {
+                    let beta = (k / (3.0 * next.visits + k)).sqrt();
+                    let value = (1.0 - beta) * next.wins[player_id - 1] / visits + beta * next.amaf_wins[player_id - 1] / next.amaf_visits;
+
+                    value * visits
+                },
+                _ => next.wins[player_id - 1]
+            };
+
+            (i, policy.score_with_squares(wins, next.squared_wins[player_id - 1], visits, self.visits + self.virtual_loss, next.prior))
+        });
+
+        best_index(scores, &mut rand::thread_rng())
+    }
+
+    // the proven reward of a solved node for a player
+    fn reward(&self, player: usize) -> f64 {
+        self.rewards.as_ref().map_or(0.0, |rewards| rewards[player - 1])
+    }
+
+    // solves the node once a child is a proven win for the player to move, or once every choice is solved.
+    // it then takes the result of its best solved child (a win over a draw over a loss, then the higher reward)
+    fn prove(&mut self) {
+        if self.chance {
+            // luck can't be chosen, so a chance node is only solved if every outcome leads to the same result
+            if self.next.iter().all(|next| next.outcome.is_some() && next.outcome == self.next[0].outcome) {
+                let mut rewards = vec![0.0; self.wins.len()];
+
+                for next in self.next.iter() {
+                    rewards.iter_mut().zip(next.rewards.iter().flatten()).for_each(|(reward, next_reward)| *reward += next.prior * next_reward);
+                }
+
+                self.outcome = self.next[0].outcome.clone();
+                self.rewards = Some(rewards);
+            }
+
+            return;
+        }
+
+        let player = self.turn;
+
+        let rank = |next: &Self| match &next.outcome {
+            Some(outcome) if outcome.is_win_for(player) => 2,
+            Some(outcome) if !outcome.is_loss_for(player) => 1,
+            _ => 0
+        };
+
+        if !self.next.iter().any(|next| rank(next) == 2) && (self.partial || !self.next.iter().all(|next| next.outcome.is_some())) {
+            return;
+        }
+
+        if let Some(best) = self.next.iter()
+            .filter(|next| next.outcome.is_some())
+            .max_by(|a, b| rank(a).cmp(&rank(b)).then(a.reward(player).total_cmp(&b.reward(player)))) {
+            self.outcome = best.outcome.clone();
+            self.rewards = best.rewards.clone();
+        }
+    }
+
+    fn update(&mut self, values: Vec<f64>) -> Vec<f64> {
+        self.wins.iter_mut().zip(values.iter()).for_each(|(win, value)| *win += value);
+        self.squared_wins.iter_mut().zip(values.iter()).for_each(|(win, value)| *win += value * value);
+        self.visits += 1.0;
+
+        values
+    }
+
+    // counts the cycle for every child whose choice the player to move made at some point in `played`
+    fn update_amaf<'a>(&mut self, values: &[f64], played: impl Iterator<Item = &'a (usize, Choice)> + Clone) where Choice: PartialEq + 'a {
+        let player = self.turn;
+
+        for next in self.next.iter_mut() {
+            if played.clone().any(|(by, choice)| *by == player && next.choice.as_ref() == Some(choice)) {
+                next.amaf_wins.iter_mut().zip(values.iter()).for_each(|(win, value)| *win += value);
+                next.amaf_visits += 1.0;
+            }
+        }
+    }
+
+    pub fn choose(&mut self, choice: &Choice) where Choice: PartialEq {
+        let chosen_node_index = self.next
+            .iter()
+            .position(|node| {
+                if let Some(node_choice) = node.choice.clone() { &node_choice == choice } else { false }
+            }).expect("The node does not include this choice");
+
+        self.create_next_state(chosen_node_index);
+        *self = self.next.remove(chosen_node_index);
+    }
+
+    // the proven outcome of the position, if the search has solved it
+    pub fn outcome(&self) -> Option<&Outcome> {
+        self.outcome.as_ref()
+    }
+
+    // the number of nodes in the tree
+    pub fn size(&self) -> usize {
+        let mut size = 0;
+        let mut stack = vec![self];
+
+        while let Some(node) = stack.pop() {
+            size += 1;
+            stack.extend(node.next.iter());
+        }
+
+        size
+    }
+
+    // adds the statistics of another tree searched from the same position into this one. the trees are walked
+    // children first with an explicit stack, so every merged parent can be proven again from its merged children.
+    // keyed nodes hold the totals of the transposition table they were searched with, so only trees that were
+    // searched with separate tables (like the ones `MCTS::advise_root_parallel` builds) add up correctly
+    pub fn merge(&mut self, mut other: Self) where Choice: PartialEq {
+        let matched = self.merge_node(&mut other);
+        let next = std::mem::take(&mut self.next);
+
+        // each frame is a merged node waiting on its children, the children still to merge and the ones that are done
+        let mut stack = vec![MergeFrame { node: None, pending: next.into_iter().zip(matched).collect::<Vec<_>>().into_iter(), done: Vec::new() }];
+
+        while let Some(frame) = stack.last_mut() {
+            match frame.pending.next() {
+                Some((mut next, Some(mut other_next))) => {
+                    let matched = next.merge_node(&mut other_next);
+                    let pending = std::mem::take(&mut next.next).into_iter().zip(matched).collect::<Vec<_>>().into_iter();
+
+                    stack.push(MergeFrame { node: Some(next), pending, done: Vec::new() });
+                },
+                Some((next, None)) => frame.done.push(next),
+                None => {
+                    let frame = stack.pop().unwrap();
+
+                    match (frame.node, stack.last_mut()) {
+                        (Some(mut node), Some(parent)) => {
+                            node.next = frame.done;
+                            node.reprove();
+                            parent.done.push(node);
+                        },
+                        _ => {
+                            self.next = frame.done;
+                            self.reprove();
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // adds the other node's statistics into this one and moves over its children with new choices, returning the
+    // other's children that match this one's (in the same order)
+    fn merge_node(&mut self, other: &mut Self) -> Vec<Option<Self>> where Choice: PartialEq {
+        self.wins.iter_mut().zip(other.wins.iter()).for_each(|(win, other_win)| *win += other_win);
+        self.squared_wins.iter_mut().zip(other.squared_wins.iter()).for_each(|(win, other_win)| *win += other_win);
+        self.visits += other.visits;
+        self.amaf_wins.iter_mut().zip(other.amaf_wins.iter()).for_each(|(win, other_win)| *win += other_win);
+        self.amaf_visits += other.amaf_visits;
+
+        if self.outcome.is_none() {
+            self.outcome = other.outcome.take();
+            self.rewards = other.rewards.take();
+        }
+
+        if self.game_state.is_none() {
+            self.key = other.key;
+            self.game_state = other.game_state.take();
+        }
+
+        // only the other node was expanded, so what it learnt about the position comes over with its children
+        if self.next.is_empty() && !other.next.is_empty() {
+            self.turn = other.turn;
+            self.chance = other.chance;
+            self.partial = other.partial;
+        }
+
+        if self.priors.is_none() {
+            self.priors = other.priors.take();
+        }
+
+        let mut matched: Vec<Option<Self>> = self.next.iter().map(|_| None).collect();
+
+        for other_next in std::mem::take(&mut other.next) {
+            match self.next.iter().position(|next| next.choice == other_next.choice) {
+                Some(index) => matched[index] = Some(other_next),
+                None => {
+                    self.next.push(other_next);
+                    matched.push(None);
+                }
+            }
+        }
+
+        matched
+    }
+
+    // children solved in different trees can solve their parent once the trees are merged
+    fn reprove(&mut self) {
+        if self.outcome.is_none() && self.next.iter().any(|next| next.outcome.is_some()) {
+            self.prove();
+        }
+    }
+}
+
+// a child and the other tree's child with the same choice, if it has one
+type MergePair<Game_, Choice> = (Node<Game_, Choice>, Option<Node<Game_, Choice>>);
+
+struct MergeFrame<Game_, Choice> where Game_: Game<Choice> + Clone, Choice: Clone {
+    node: Option<Node<Game_, Choice>>,
+    pending: std::vec::IntoIter<MergePair<Game_, Choice>>,
+    done: Vec<Node<Game_, Choice>>
+}
+
+impl<Game_, Choice> Drop for Node<Game_, Choice> where Game_: Game<Choice> + Clone, Choice: Clone {
+    fn drop(&mut self) {
+        drop_tree(std::mem::take(&mut self.next), |node: &mut Self| std::mem::take(&mut node.next));
+    }
+}
+
+impl<Game_, Choice> std::fmt::Display for Node<Game_, Choice> where Game_: Game<Choice> + Clone, Choice: Clone + std::fmt::Debug {
+    // written with an explicit stack (of nodes, or text to write between them) so deep trees can be printed
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut stack: Vec<Result<&Self, &str>> = vec![Ok(self)];
+
+        while let Some(item) = stack.pop() {
+            let node = match item {
+                Ok(node) => node,
+                Err(text) => {
+                    write!(f, "{}", text)?;
+
+                    continue;
+                }
+            };
+
+            if let Some(outcome) = &node.outcome { 
+                write!(f, "{{\"choice\": \"{:?}\",  \"outcome\": \"{:?}\", \"visits\": {}}}", node.choice, outcome, node.visits)?;
+            } else if node.next.is_empty() && node.visits == 0.0 {
+                write!(f, "{{}}")?;
+            } else if node.next.is_empty() {
+                write!(f, "{{\"choice\": \"{:?}\",  \"wins\": {:?}, \"visits\": {}}}", node.choice, node.wins, node.visits)?;
+            } else {
+                write!(f, "{{\"choice\": \"{:?}\", \"wins\": {:?}, \"visits\": {}, \"next\": [", node.choice, node.wins, node.visits)?;
+
+                stack.push(Err("]}"));
+
+                for (i, next) in node.next.iter().enumerate().rev() {
+                    stack.push(Ok(next));
+
+                    if i > 0 { stack.push(Err(", ")) };
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // take 1 to 3 from the pile, whoever takes the last one wins
+    #[derive(Clone, Debug, PartialEq)]
+    struct Nim { left: usize, turn: usize }
+
+    impl Game<usize> for Nim {
+        fn get_num_players(&self) -> usize { 2 }
+        fn get_turn(&self) -> usize { self.turn }
+        fn get_choices(&self) -> Vec<usize> { (1..=self.left.min(3)).collect() }
+        fn choose(&mut self, choice: &usize) { self.left -= choice; self.turn = 3 - self.turn; }
+        fn get_outcome(&self) -> Outcome { if self.left == 0 { Outcome::Win(3 - self.turn) } else { Outcome::Ongoing } }
+        fn get_key(&self) -> Option<u64> { Some((self.left * 2 + self.turn) as u64) }
+    }
+
+    impl Undo<usize> for Nim {
+        fn undo(&mut self, choice: &usize) { self.left += choice; self.turn = 3 - self.turn; }
+    }
+
+    // nim with playouts that player 1 always wins
+    #[derive(Clone)]
+    struct Rigged(Nim);
+
+    impl Game<usize> for Rigged {
+        fn get_num_players(&self) -> usize { 2 }
+        fn get_turn(&self) -> usize { self.0.turn }
+        fn get_choices(&self) -> Vec<usize> { self.0.get_choices() }
+        fn choose(&mut self, choice: &usize) { self.0.choose(choice) }
+        fn get_outcome(&self) -> Outcome { self.0.get_outcome() }
+
+        fn playout(&mut self, _max_length: Option<usize>, _played: Option<&mut Vec<(usize, usize)>>) -> Vec<f64> {
+            vec![1.0, 0.0]
+        }
+    }
+
+    // player 1 takes a draw (0) or a gamble (1) that they win 3 times in 10
+    #[derive(Clone)]
+    struct Gamble { choice: Option<usize>, won: Option<bool> }
+
+    impl Game<usize> for Gamble {
+        fn get_num_players(&self) -> usize { 2 }
+        fn get_turn(&self) -> usize { 1 }
+        fn get_choices(&self) -> Vec<usize> { vec![0, 1] }
+
+        fn choose(&mut self, choice: &usize) {
+            if self.choice.is_none() { self.choice = Some(*choice) } else { self.won = Some(*choice == 1) }
+        }
+
+        fn get_outcome(&self) -> Outcome {
+            match (self.choice, self.won) {
+                (Some(0), _) => Outcome::Draw,
+                (_, Some(won)) => Outcome::Win(if won { 1 } else { 2 }),
+                _ => Outcome::Ongoing
+            }
+        }
+
+        fn get_chance_outcomes(&self) -> Option<Vec<(usize, f64)>> {
+            if self.choice == Some(1) && self.won.is_none() { Some(vec![(0, 0.7), (1, 0.3)]) } else { None }
+        }
+    }
+
+    // a coin is tossed, then player 1 wins by guessing how a second toss lands
+    #[derive(Clone)]
+    struct Guess { tosses: Vec<usize>, guess: Option<usize> }
+
+    impl Game<usize> for Guess {
+        fn get_num_players(&self) -> usize { 2 }
+        fn get_turn(&self) -> usize { 1 }
+        fn get_choices(&self) -> Vec<usize> { vec![0, 1] }
+
+        fn choose(&mut self, choice: &usize) {
+            if self.get_chance_outcomes().is_some() { self.tosses.push(*choice) } else { self.guess = Some(*choice) }
+        }
+
+        fn get_outcome(&self) -> Outcome {
+            match (self.guess, self.tosses.get(1)) {
+                (Some(guess), Some(&toss)) => Outcome::Win(if guess == toss { 1 } else { 2 }),
+                _ => Outcome::Ongoing
+            }
+        }
+
+        fn get_chance_outcomes(&self) -> Option<Vec<(usize, f64)>> {
+            if self.tosses.is_empty() || self.guess.is_some() { Some(vec![(0, 1.0), (1, 1.0)]) } else { None }
+        }
+    }
+
+    // a child of the nim root with 4 left (where every choice loses) that's already been solved
+    fn lost_child(choice: usize) -> Node<Nim, usize> {
+        let mut next = Node::new(Some(choice), 1.0 / 3.0, 2);
+        next.outcome = Some(Outcome::Win(2));
+        next.rewards = Some(vec![0.0, 1.0]);
+
+        next
+    }
+
+    #[test]
+    fn forced_win_is_solved() {
+        // taking 1 leaves 4, which loses whatever the other player takes
+        let game_state = Nim { left: 5, turn: 1 };
+        let monte = MCTS::new(&game_state, ExploitVsExplore::UCB1(1.4));
+        let mut tree = Node::default(game_state, 2);
+
+        assert_eq!(monte.advise_with_tree(&mut tree, 10000), Some(1));
+        assert_eq!(tree.outcome(), Some(&Outcome::Win(1)));
+        assert!(tree.visits < 10000.0);
+    }
+
+    #[test]
+    fn undo_returns_the_state() {
+        let game_state = Nim { left: 21, turn: 1 };
+        let monte = MCTS::new(&game_state, ExploitVsExplore::UCB1(1.4)).with_undo();
+        let mut tree = Node::default(game_state.clone(), 2);
+
+        monte.advise_with_tree(&mut tree, 1000);
+
+        assert_eq!(tree.game_state(), Some(&game_state));
+        // only the root keeps a state
+        assert!(tree.next.iter().all(|next| next.game_state().is_none()));
+    }
+
+    #[test]
+    fn searches_keep_the_tree() {
+        let game_state = Nim { left: 21, turn: 1 };
+        let monte = MCTS::new(&game_state, ExploitVsExplore::UCB1(1.4));
+        let mut tree = Node::default(game_state, 2);
+
+        monte.advise_with_tree(&mut tree, 500);
+        monte.advise_with_tree(&mut tree, 1);
+
+        assert_eq!(tree.visits, 501.0);
+    }
+
+    #[test]
+    fn searches_are_independent() {
+        let game_state = Nim { left: 40, turn: 1 };
+        let monte = MCTS::new(&game_state, ExploitVsExplore::UCB1(1.4));
+
+        // a fresh tree doesn't pick up the visits of the searches before it
+        for _ in 0..3 {
+            let mut tree = Node::default(game_state.clone(), 2);
+            monte.advise_with_tree(&mut tree, 100);
+
+            assert_eq!(tree.visits, 100.0);
+        }
+    }
+
+    #[test]
+    fn rave_uses_the_playout() {
+        // too far from the end of the game for any cycle to reach it, so every value comes from a playout
+        let game_state = Rigged(Nim { left: 40, turn: 1 });
+
+        for monte in [MCTS::new(&game_state, ExploitVsExplore::UCB1(1.4)), MCTS::new(&game_state, ExploitVsExplore::UCB1(1.4)).with_rave(100.0)] {
+            let mut tree = Node::default(game_state.clone(), 2);
+            monte.advise_with_tree(&mut tree, 50);
+
+            assert_eq!(tree.wins, vec![50.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn final_selection_takes_the_proven_draw() {
+        let game_state = Gamble { choice: None, won: None };
+
+        for final_selection in [FinalSelection::Max, FinalSelection::Robust, FinalSelection::MaxRobust, FinalSelection::Secure(1.0)] {
+            let monte = MCTS::new(&game_state, ExploitVsExplore::UCB1(1.4)).with_final_selection(final_selection);
+
+            for _ in 0..5 {
+                let mut tree = Node::default(game_state.clone(), 2);
+                assert_eq!(monte.advise_with_tree(&mut tree, 2000), Some(0));
+
+                // max robust doesn't search on trying to settle a choice the proven draw has already settled
+                assert_eq!(tree.visits, 2000.0);
+            }
+        }
+    }
+
+    #[test]
+    fn sampling_takes_the_proven_draw() {
+        let game_state = Gamble { choice: None, won: None };
+        let monte = MCTS::new(&game_state, ExploitVsExplore::UCB1(1.4));
+
+        for temperature in [0.0, 0.1, 1.0] {
+            for _ in 0..5 {
+                assert_eq!(monte.advise_sampled(&game_state, 2000, temperature), Some(0));
+            }
+        }
+    }
+
+    #[test]
+    fn merge_keeps_chance() {
+        let game_state = Guess { tosses: Vec::new(), guess: None };
+        let monte = MCTS::new(&game_state, ExploitVsExplore::UCB1(1.4));
+        let mut tree = Node::default(game_state.clone(), 2);
+        let mut other = Node::default(game_state, 2);
+
+        monte.advise_with_tree(&mut other, 100);
+        tree.merge(other);
+
+        assert!(tree.chance);
+        assert_eq!(tree.visits, 100.0);
+
+        // and the merged tree can be searched further
+        monte.advise_with_tree(&mut tree, 100);
+        assert_eq!(tree.visits, 200.0);
+    }
+
+    #[test]
+    fn merge_keeps_partial() {
+        let game_state = Nim { left: 20, turn: 1 };
+        let monte = MCTS::new(&game_state, ExploitVsExplore::UCB1(1.4)).with_progressive_widening(1.0, 0.5);
+        let mut tree = Node::default(game_state.clone(), 2);
+        let mut other = Node::default(game_state, 2);
+
+        monte.advise_with_tree(&mut other, 100);
+        tree.merge(other);
+
+        assert!(tree.partial);
+
+        monte.advise_with_tree(&mut tree, 100);
+        assert_eq!(tree.visits, 200.0);
+    }
+
+    #[test]
+    fn merge_proves_parent() {
+        let game_state = Nim { left: 4, turn: 1 };
+        let mut tree = Node::default(game_state.clone(), 2);
+        let mut other = Node::default(game_state, 2);
+
+        // each tree has only solved some of the choices
+        tree.turn = 1;
+        tree.next = vec![lost_child(1), lost_child(2), Node::new(Some(3), 1.0 / 3.0, 2)];
+        other.turn = 1;
+        other.next = vec![Node::new(Some(1), 1.0 / 3.0, 2), lost_child(3)];
+
+        tree.merge(other);
+
+        assert_eq!(tree.outcome(), Some(&Outcome::Win(2)));
+        assert_eq!(tree.rewards, Some(vec![0.0, 1.0]));
+    }
+
+    #[test]
+    fn ucb1_score() {
+        let score = ExploitVsExplore::UCB1(1.4).score(3.0, 4.0, 10.0, 0.0);
+
+        assert!((score - 1.8121989905696025).abs() < 1e-12);
+    }
+
+    #[test]
+    fn kl_ucb_score() {
+        // bisected to 16 iterations, so only good to about 1e-5
+        let score = ExploitVsExplore::KLUCB(0.0).score(5.0, 10.0, 100.0, 0.0);
+        assert!((score - 0.8879087616458613).abs() < 1e-4);
+
+        let score = ExploitVsExplore::KLUCB(3.0).score(6.0, 20.0, 50.0, 0.0);
+        assert!((score - 0.7300582511038632).abs() < 1e-4);
+
+        // a child that has always won can't be bounded below 1
+        assert_eq!(ExploitVsExplore::KLUCB(0.0).score(2.0, 2.0, 1000.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn squared_wins_set_the_variance() {
+        // every reward 0.5, so there's no variance (it would be 0.25 if the squares were taken to be the wins)
+        let score = ExploitVsExplore::UCB1Tuned.score_with_squares(500.0, 250.0, 1000.0, 1000.0, 0.0);
+
+        assert!((score - 0.5284944452657544).abs() < 1e-12);
+    }
+}
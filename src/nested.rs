@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+use crate::{Game, Outcome, Solution, sample_weighted};
+use crate::single_player::{random_play_recorded, record};
+
+// nested monte carlo search: at each step of the game every choice is tried with a search one level lower, and
+// the best line found so far is followed. level 0 is a plain random playout. maximises player 1's reward
+pub struct NestedMonteCarlo<Game_, Choice> where Choice: Clone, Game_: Game<Choice> + Clone {
+    level: usize,
+    __anoying: (PhantomData<Choice>, PhantomData<Game_>)
+}
+
+impl<Game_, Choice> NestedMonteCarlo<Game_, Choice> where Choice: Clone, Game_: Game<Choice> + Clone {
+    // the work grows by roughly the branching factor times the game length per level, 2 or 3 is usually plenty
+    pub fn new(level: usize) -> Self {
+        Self { level, __anoying: (PhantomData, PhantomData) }
+    }
+
+    fn nested(&self, game_state: &Game_, level: usize) -> Solution<Choice> {
+        let mut game_state = game_state.clone();
+        let mut line = Vec::new();
+
+        if level == 0 {
+            let score = random_play_recorded(&mut game_state, &mut line);
+
+            return Solution { choices: line, score };
+        }
+
+        let mut best: Option<Solution<Choice>> = None;
+
+        while game_state.get_outcome() == Outcome::Ongoing {
+            let choices = game_state.get_choices();
+
+            if choices.is_empty() { break };
+
+            for choice in choices {
+                let mut next_game_state = game_state.clone();
+                next_game_state.choose(&choice);
+
+                let result = self.nested(&next_game_state, level - 1);
+                let mut result_line = line.clone();
+                result_line.push(choice);
+                result_line.extend(result.choices);
+
+                record(&mut best, &result_line, result.score);
+            }
+
+            let choice = best.as_ref().unwrap().choices[line.len()].clone();
+            game_state.choose(&choice);
+            line.push(choice);
+        }
+
+        best.unwrap_or_else(|| Solution { choices: line, score: game_state.get_rewards()[0] })
+    }
+
+    pub fn solve(&self, game_state: &Game_) -> Solution<Choice> {
+        self.nested(game_state, self.level)
+    }
+
+    pub fn advise(&self, game_state: &Game_) -> Option<Choice> {
+        self.solve(game_state).choices.into_iter().next()
+    }
+}
+
+// nested rollout policy adaptation: playouts follow a softmax policy over the choices, and each level repeatedly
+// runs the level below and pulls the policy towards the best line found. maximises player 1's reward. the policy
+// weights are kept per move code, which is the choice itself unless `with_code` gives one that depends on the
+// position (like the previous and next city in a tour)
+pub struct NRPA<Game_, Choice, Code = Choice> where Choice: Clone, Game_: Game<Choice> + Clone, Code: Hash + Eq + Clone {
+    level: usize,
+    iterations: usize,
+    alpha: f64,
+    code: fn(&Game_, &Choice) -> Code,
+    __anoying: (PhantomData<Choice>, PhantomData<Game_>)
+}
+
+type Policy<Code> = HashMap<Code, f64>;
+
+impl<Game_, Choice> NRPA<Game_, Choice> where Choice: Clone + Hash + Eq, Game_: Game<Choice> + Clone {
+    // the usual settings are 100 iterations per level with an alpha (learning rate) of 1
+    pub fn new(level: usize, iterations: usize, alpha: f64) -> Self {
+        Self { level, iterations, alpha, code: |_, choice| choice.clone(), __anoying: (PhantomData, PhantomData) }
+    }
+}
+
+impl<Game_, Choice, Code> NRPA<Game_, Choice, Code> where Choice: Clone, Game_: Game<Choice> + Clone, Code: Hash + Eq + Clone {
+    // keys the policy by the code of a choice in the position it's made from
+    pub fn with_code<NewCode>(self, code: fn(&Game_, &Choice) -> NewCode) -> NRPA<Game_, Choice, NewCode> where NewCode: Hash + Eq + Clone {
+        NRPA { level: self.level, iterations: self.iterations, alpha: self.alpha, code, __anoying: (PhantomData, PhantomData) }
+    }
+
+    fn playout(&self, game_state: &Game_, policy: &Policy<Code>) -> Solution<Choice> {
+        let mut game_state = game_state.clone();
+        let mut line = Vec::new();
+        let mut rng = rand::thread_rng();
+
+        while game_state.get_outcome() == Outcome::Ongoing {
+            let choices = game_state.get_choices();
+
+            if choices.is_empty() { break };
+
+            let weights: Vec<f64> = choices.iter().map(|choice| policy.get(&(self.code)(&game_state, choice)).unwrap_or(&0.0).exp()).collect();
+            let index = sample_weighted(weights.into_iter(), &mut rng);
+            game_state.choose(&choices[index]);
+            line.push(choices[index].clone());
+        }
+
+        Solution { choices: line, score: game_state.get_rewards()[0] }
+    }
+
+    // replays the line, raising the weight of each choice made and lowering the others in proportion to how
+    // likely the policy was to pick them
+    fn adapt(&self, game_state: &Game_, policy: &Policy<Code>, line: &[Choice]) -> Policy<Code> {
+        let mut game_state = game_state.clone();
+        let mut adapted = policy.clone();
+
+        for chosen in line {
+            let choices = game_state.get_choices();
+            let codes: Vec<Code> = choices.iter().map(|choice| (self.code)(&game_state, choice)).collect();
+            let weights: Vec<f64> = codes.iter().map(|code| policy.get(code).unwrap_or(&0.0).exp()).collect();
+            let total: f64 = weights.iter().sum();
+
+            *adapted.entry((self.code)(&game_state, chosen)).or_insert(0.0) += self.alpha;
+
+            for (code, weight) in codes.into_iter().zip(weights) {
+                *adapted.entry(code).or_insert(0.0) -= self.alpha * weight / total;
+            }
+
+            game_state.choose(chosen);
+        }
+
+        adapted
+    }
+
+    fn nrpa(&self, game_state: &Game_, level: usize, policy: Policy<Code>) -> Solution<Choice> {
+        if level == 0 {
+            return self.playout(game_state, &policy);
+        }
+
+        let mut policy = policy;
+        let mut best: Option<Solution<Choice>> = None;
+
+        for _ in 0..self.iterations {
+            let result = self.nrpa(game_state, level - 1, policy.clone());
+
+            if best.as_ref().is_none_or(|best| result.score >= best.score) {
+                best = Some(result);
+            }
+
+            policy = self.adapt(game_state, &policy, &best.as_ref().unwrap().choices);
+        }
+
+        best.unwrap_or_else(|| self.playout(game_state, &policy))
+    }
+
+    pub fn solve(&self, game_state: &Game_) -> Solution<Choice> {
+        self.nrpa(game_state, self.level, HashMap::new())
+    }
+
+    pub fn advise(&self, game_state: &Game_) -> Option<Choice> {
+        self.solve(game_state).choices.into_iter().next()
+    }
+}
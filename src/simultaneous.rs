@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::time::Instant;
+
+use crate::{Budget, Evaluator, ExploitVsExplore, Game, Outcome, RandomPlayout, SelectionPolicy, best_index, drop_tree, sample_weighted};
+
+// how each player's bandit picks its choice, independently of what the other players pick
+pub enum DecoupledPolicy {
+    UCT(ExploitVsExplore),
+    // the exploration rate gamma, plays a mixed strategy so it can't be exploited in games like rock paper scissors
+    Exp3(f64)
+}
+
+// decoupled mcts for games with simultaneous phases (see `Game::get_simultaneous_choices`): every node keeps a
+// separate bandit per player, and the children are reached by the joint choice of everyone who moved
+pub struct DecoupledMCTS<Game_, Choice, Eval = RandomPlayout> where Choice: Clone, Game_: Game<Choice> + Clone, Eval: Evaluator<Game_, Choice> {
+    players: usize,
+    policy: DecoupledPolicy,
+    evaluator: Eval,
+    __anoying: (PhantomData<Choice>, PhantomData<Game_>)
+}
+
+impl<Game_, Choice> DecoupledMCTS<Game_, Choice> where Choice: Clone, Game_: Game<Choice> + Clone {
+    pub fn new(initial_game_state: &Game_, policy: DecoupledPolicy) -> Self {
+        Self::with_evaluator(initial_game_state, policy, RandomPlayout::new())
+    }
+}
+
+impl<Game_, Choice, Eval> DecoupledMCTS<Game_, Choice, Eval> where Choice: Clone, Game_: Game<Choice> + Clone, Eval: Evaluator<Game_, Choice> {
+    pub fn with_evaluator(initial_game_state: &Game_, policy: DecoupledPolicy, evaluator: Eval) -> Self {
+        let players = initial_game_state.get_num_players();
+
+        Self { players, policy, evaluator, __anoying: (PhantomData, PhantomData) }
+    }
+
+    // a bandit per player, in a turn based phase only the player to move gets any arms
+    fn expand(&self, node: &mut JointNode<Choice>, game_state: &Game_) {
+        match game_state.get_simultaneous_choices() {
+            Some(player_choices) => {
+                node.simultaneous = true;
+                node.arms = player_choices.into_iter().map(|choices| choices.into_iter().map(Arm::new).collect()).collect();
+            },
+            None => {
+                node.arms = (0..self.players).map(|_| Vec::new()).collect();
+                node.arms[game_state.get_turn() - 1] = game_state.get_choices().into_iter().map(Arm::new).collect();
+            }
+        }
+
+        node.expanded = true;
+    }
+
+    // returns the arm picked and the probability it had of being picked
+    fn pick(&self, arms: &[Arm<Choice>], parent_visits: f64) -> Option<(usize, f64)> {
+        if arms.is_empty() { return None };
+
+        let mut rng = rand::thread_rng();
+
+        match &self.policy {
+            DecoupledPolicy::UCT(exploit_vs_explore) => {
+                let prior = 1.0 / arms.len() as f64;
+                let scores = arms.iter().enumerate().map(|(i, arm)| {
+                    (i, exploit_vs_explore.score_with_squares(arm.wins, arm.squared_wins, arm.visits + 0.00001, parent_visits, prior))
+                });
+
+                best_index(scores, &mut rng).map(|index| (index, 1.0))
+            },
+            DecoupledPolicy::Exp3(gamma) => {
+                let probabilities = exp3_probabilities(arms, *gamma);
+                let index = sample_weighted(probabilities.iter().copied(), &mut rng);
+
+                Some((index, probabilities[index]))
+            }
+        }
+    }
+
+    // a cycle from the position in `game_state`, recording in `path` the key and picks of every step down the tree
+    fn search(&self, tree: &mut JointNode<Choice>, game_state: &mut Game_, path: &mut Vec<Step>, nodes: &mut usize) {
+        path.clear();
+
+        let values = self.descend(tree, game_state, path, nodes);
+
+        Self::backup(tree, path, &values);
+    }
+
+    // plays the joint picks (or chance outcomes) down to a node that hasn't been visited, which is evaluated, or
+    // to the end of the game. returns the value each player got
+    fn descend(&self, tree: &mut JointNode<Choice>, game_state: &mut Game_, path: &mut Vec<Step>, nodes: &mut usize) -> Vec<f64> {
+        let mut node = tree;
+
+        loop {
+            if node.visits < 1.0 {
+                return self.evaluator.evaluate(game_state).values;
+            }
+
+            if game_state.get_outcome() != Outcome::Ongoing {
+                return game_state.get_rewards();
+            }
+
+            let mut picks = Vec::new();
+
+            let key = if let Some(outcomes) = game_state.get_chance_outcomes() {
+                if outcomes.is_empty() {
+                    return game_state.get_rewards();
+                }
+
+                let index = sample_weighted(outcomes.iter().map(|(_, weight)| *weight), &mut rand::thread_rng());
+                game_state.choose(&outcomes[index].0);
+
+                vec![Some(index)]
+            } else {
+                if !node.expanded {
+                    self.expand(node, game_state);
+                }
+
+                if node.arms.iter().all(|arms| arms.is_empty()) {
+                    return game_state.get_rewards();
+                }
+
+                picks = node.arms.iter().map(|arms| self.pick(arms, node.visits)).collect();
+
+                let joint: Vec<Option<Choice>> = picks.iter().zip(node.arms.iter())
+                    .map(|(pick, arms)| pick.map(|(index, _)| arms[index].choice.clone()))
+                    .collect();
+
+                if node.simultaneous {
+                    game_state.choose_simultaneous(&joint);
+                } else if let Some(choice) = joint.iter().flatten().next() {
+                    game_state.choose(choice);
+                }
+
+                picks.iter().map(|pick| pick.map(|(index, _)| index)).collect()
+            };
+
+            node = node.next.entry(key.clone()).or_insert_with(|| {
+                *nodes += 1;
+
+                JointNode::new(self.players)
+            });
+            path.push((key, picks));
+        }
+    }
+
+    fn backup(tree: &mut JointNode<Choice>, path: &[Step], values: &[f64]) {
+        let mut node = tree;
+
+        for (key, picks) in path {
+            for (player, pick) in picks.iter().enumerate() {
+                if let Some((index, probability)) = pick {
+                    node.arms[player][*index].update(values[player], *probability);
+                }
+            }
+
+            node.update(values);
+            node = node.next.get_mut(key).unwrap();
+        }
+
+        node.update(values);
+    }
+
+    fn search_tree(&self, game_state: &Game_, budget: &Budget) -> JointNode<Choice> {
+        let mut tree = JointNode::new(self.players);
+        let start = Instant::now();
+        let mut path = Vec::new();
+        let mut nodes = 1;
+        let mut cycles = 0;
+
+        while !budget.is_exhausted(cycles, start, nodes) {
+            self.search(&mut tree, &mut game_state.clone(), &mut path, &mut nodes);
+            cycles += 1;
+        }
+
+        tree
+    }
+
+    // the most visited arm, or for exp3 an arm drawn from the mixed strategy given by the visits
+    fn final_pick(&self, arms: &[Arm<Choice>]) -> Option<Choice> {
+        if arms.is_empty() { return None };
+
+        let index = match self.policy {
+            DecoupledPolicy::UCT(_) => (0..arms.len()).max_by(|&a, &b| arms[a].visits.total_cmp(&arms[b].visits)).unwrap(),
+            DecoupledPolicy::Exp3(_) => sample_weighted(arms.iter().map(|arm| arm.visits), &mut rand::thread_rng())
+        };
+
+        Some(arms[index].choice.clone())
+    }
+
+    // the choice for one player, None if they have nothing to choose right now
+    pub fn advise(&self, game_state: &Game_, player: usize, cycles: usize) -> Option<Choice> {
+        self.advise_with_budget(game_state, player, &Budget::new().with_cycles(cycles))
+    }
+
+    pub fn advise_with_budget(&self, game_state: &Game_, player: usize, budget: &Budget) -> Option<Choice> {
+        let tree = self.search_tree(game_state, budget);
+
+        tree.arms.get(player - 1).and_then(|arms| self.final_pick(arms))
+    }
+
+    // a choice for every player (player 1 first), ready for `Game::choose_simultaneous`
+    pub fn advise_joint(&self, game_state: &Game_, cycles: usize) -> Option<Vec<Option<Choice>>> {
+        let tree = self.search_tree(game_state, &Budget::new().with_cycles(cycles));
+        let joint: Vec<Option<Choice>> = tree.arms.iter().map(|arms| self.final_pick(arms)).collect();
+
+        if joint.iter().all(|choice| choice.is_none()) { None } else { Some(joint) }
+    }
+}
+
+fn exp3_probabilities<Choice>(arms: &[Arm<Choice>], gamma: f64) -> Vec<f64> {
+    let k = arms.len() as f64;
+    let eta = gamma / k;
+    let max_gain = arms.iter().map(|arm| arm.gain).fold(f64::NEG_INFINITY, f64::max);
+
+    let weights: Vec<f64> = arms.iter().map(|arm| (eta * (arm.gain - max_gain)).exp()).collect();
+    let total: f64 = weights.iter().sum();
+
+    weights.iter().map(|weight| (1.0 - gamma) * weight / total + gamma / k).collect()
+}
+
+struct Arm<Choice> {
+    choice: Choice,
+    wins: f64,
+    squared_wins: f64,
+    visits: f64,
+    // importance weighted sum of the rewards, used by exp3
+    gain: f64
+}
+
+impl<Choice> Arm<Choice> {
+    fn new(choice: Choice) -> Self {
+        Arm { choice, wins: 0.0, squared_wins: 0.0, visits: 0.0, gain: 0.0 }
+    }
+
+    fn update(&mut self, value: f64, probability: f64) {
+        self.wins += value;
+        self.squared_wins += value * value;
+        self.visits += 1.0;
+        self.gain += value / probability;
+    }
+}
+
+// the key of the child a cycle went to, and the arm each player picked to get there (with the chance it had of
+// being picked)
+type Step = (Vec<Option<usize>>, Vec<Option<(usize, f64)>>);
+
+struct JointNode<Choice> {
+    expanded: bool,
+    simultaneous: bool,
+    wins: Vec<f64>,
+    visits: f64,
+    // one bandit per player, indexed by player - 1
+    arms: Vec<Vec<Arm<Choice>>>,
+    // keyed by the arm each player picked (or the chance outcome drawn)
+    next: HashMap<Vec<Option<usize>>, JointNode<Choice>>
+}
+
+impl<Choice> JointNode<Choice> {
+    fn new(players: usize) -> Self {
+        JointNode { expanded: false, simultaneous: false, wins: vec![0.0; players], visits: 0.0, arms: Vec::new(), next: HashMap::new() }
+    }
+
+    fn update(&mut self, values: &[f64]) {
+        self.wins.iter_mut().zip(values.iter()).for_each(|(win, value)| *win += value);
+        self.visits += 1.0;
+    }
+}
+
+impl<Choice> Drop for JointNode<Choice> {
+    fn drop(&mut self) {
+        drop_tree(std::mem::take(&mut self.next).into_values(), |node: &mut Self| std::mem::take(&mut node.next).into_values());
+    }
+}
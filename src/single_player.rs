@@ -0,0 +1,164 @@
+use std::marker::PhantomData;
+use std::time::Instant;
+use rand::Rng;
+
+use crate::{Budget, Game, Outcome, best_index, drop_tree};
+
+// the best line of play found for a one player game, from the starting position to the end of the game
+#[derive(Clone, Debug)]
+pub struct Solution<Choice> {
+    pub choices: Vec<Choice>,
+    pub score: f64
+}
+
+// single player mcts (SP-MCTS) for puzzles and optimisation problems. it maximises the score player 1 gets from
+// `Game::get_rewards` and remembers the best playout it has seen, rather than just recommending a move
+pub struct SinglePlayerMCTS<Game_, Choice> where Choice: Clone, Game_: Game<Choice> + Clone {
+    exploration_constant: f64,
+    // added to the variance term, so rarely visited nodes with a lucky score aren't trusted too much
+    deviation_constant: f64,
+    __anoying: (PhantomData<Choice>, PhantomData<Game_>)
+}
+
+impl<Game_, Choice> SinglePlayerMCTS<Game_, Choice> where Choice: Clone, Game_: Game<Choice> + Clone {
+    // both constants depend on the range of the scores, for scores between 0 and 1 try around 0.5 and 1
+    pub fn new(exploration_constant: f64, deviation_constant: f64) -> Self {
+        Self { exploration_constant, deviation_constant, __anoying: (PhantomData, PhantomData) }
+    }
+
+    fn score(&self, node: &SPNode<Choice>, parent_visits: f64) -> f64 {
+        if node.visits < 1.0 { return f64::INFINITY };
+
+        let mean = node.total / node.visits;
+        let deviation = (node.squared_total - node.visits * mean * mean + self.deviation_constant) / node.visits;
+
+        mean + self.exploration_constant * (parent_visits.ln() / node.visits).sqrt() + deviation.max(0.0).sqrt()
+    }
+
+    // one cycle from `game_state`, `path` is left holding the index of each child it went through and `line` the
+    // choices made
+    fn sp_mcts(&self, tree: &mut SPNode<Choice>, game_state: &mut Game_, path: &mut Vec<usize>, line: &mut Vec<Choice>, best: &mut Option<Solution<Choice>>, nodes: &mut usize) {
+        path.clear();
+        line.clear();
+
+        let score = self.descend(tree, game_state, path, line, nodes);
+        record(best, line, score);
+
+        let mut node = tree;
+        node.update(score);
+
+        for &index in path.iter() {
+            node = &mut node.next[index];
+            node.update(score);
+        }
+    }
+
+    // plays `game_state` down the tree and then randomly to the end of the game, returning the score
+    fn descend(&self, tree: &mut SPNode<Choice>, game_state: &mut Game_, path: &mut Vec<usize>, line: &mut Vec<Choice>, nodes: &mut usize) -> f64 {
+        let mut node = tree;
+
+        loop {
+            if node.visits < 1.0 {
+                return random_play_recorded(game_state, line);
+            }
+
+            if node.next.is_empty() && !node.terminal {
+                if game_state.get_outcome() == Outcome::Ongoing {
+                    node.next = game_state.get_choices().into_iter().map(|choice| SPNode::new(Some(choice))).collect();
+                    *nodes += node.next.len();
+                }
+
+                node.terminal = node.next.is_empty();
+            }
+
+            if node.terminal {
+                return game_state.get_rewards()[0];
+            }
+
+            let scores = node.next.iter().enumerate().map(|(i, next)| (i, self.score(next, node.visits)));
+            let next = best_index(scores, &mut rand::thread_rng()).unwrap();
+            let choice = node.next[next].choice.clone().unwrap();
+
+            game_state.choose(&choice);
+            line.push(choice);
+            path.push(next);
+            node = &mut node.next[next];
+        }
+    }
+
+    pub fn solve(&self, game_state: &Game_, cycles: usize) -> Option<Solution<Choice>> {
+        self.solve_with_budget(game_state, &Budget::new().with_cycles(cycles))
+    }
+
+    pub fn solve_with_budget(&self, game_state: &Game_, budget: &Budget) -> Option<Solution<Choice>> {
+        let mut tree = SPNode::new(None);
+        let mut best = None;
+        let start = Instant::now();
+        let mut path = Vec::new();
+        let mut line = Vec::new();
+        let mut nodes = 1;
+        let mut cycles = 0;
+
+        while !budget.is_exhausted(cycles, start, nodes) {
+            self.sp_mcts(&mut tree, &mut game_state.clone(), &mut path, &mut line, &mut best, &mut nodes);
+            cycles += 1;
+        }
+
+        best
+    }
+
+    // the first choice of the best line found
+    pub fn advise(&self, game_state: &Game_, cycles: usize) -> Option<Choice> {
+        self.solve(game_state, cycles).and_then(|solution| solution.choices.into_iter().next())
+    }
+}
+
+// plays random moves to the end of the game, adding them to `line`, and returns player 1's score
+pub(crate) fn random_play_recorded<Game_, Choice>(game_state: &mut Game_, line: &mut Vec<Choice>) -> f64 where Game_: Game<Choice>, Choice: Clone {
+    let mut rng = rand::thread_rng();
+
+    while game_state.get_outcome() == Outcome::Ongoing {
+        let choices = game_state.get_choices();
+
+        if choices.is_empty() { break };
+
+        let choice = choices[rng.gen_range(0..choices.len())].clone();
+        game_state.choose(&choice);
+        line.push(choice);
+    }
+
+    game_state.get_rewards()[0]
+}
+
+pub(crate) fn record<Choice>(best: &mut Option<Solution<Choice>>, line: &[Choice], score: f64) where Choice: Clone {
+    if best.as_ref().is_none_or(|best| score > best.score) {
+        *best = Some(Solution { choices: line.to_vec(), score });
+    }
+}
+
+struct SPNode<Choice> {
+    choice: Option<Choice>,
+    terminal: bool,
+    total: f64,
+    squared_total: f64,
+    visits: f64,
+    next: Vec<SPNode<Choice>>
+}
+
+impl<Choice> SPNode<Choice> {
+    fn new(choice: Option<Choice>) -> Self {
+        SPNode { choice, terminal: false, total: 0.0, squared_total: 0.0, visits: 0.0, next: Vec::new() }
+    }
+
+    fn update(&mut self, score: f64) {
+        self.total += score;
+        self.squared_total += score * score;
+        self.visits += 1.0;
+    }
+}
+
+impl<Choice> Drop for SPNode<Choice> {
+    fn drop(&mut self) {
+        drop_tree(std::mem::take(&mut self.next), |node: &mut Self| std::mem::take(&mut node.next));
+    }
+}